@@ -0,0 +1,7 @@
+ALTER TABLE schedule
+DROP INDEX idx_schedule_date_movie;
+
+ALTER TABLE movies
+DROP INDEX idx_movies_year,
+DROP INDEX idx_movies_director,
+DROP INDEX idx_movies_title;
@@ -0,0 +1,7 @@
+ALTER TABLE movies
+ADD INDEX idx_movies_title (title),
+ADD INDEX idx_movies_director (director),
+ADD INDEX idx_movies_year (year);
+
+ALTER TABLE schedule
+ADD INDEX idx_schedule_date_movie (date, movie_id);
@@ -0,0 +1,2 @@
+ALTER TABLE schedule
+DROP COLUMN change_cutoff_minutes;
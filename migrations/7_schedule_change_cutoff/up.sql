@@ -0,0 +1,2 @@
+ALTER TABLE schedule
+ADD change_cutoff_minutes INT NULL;
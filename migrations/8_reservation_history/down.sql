@@ -0,0 +1,19 @@
+UPDATE reservation SET moved_from_id = NULL;
+DELETE FROM reservation WHERE status <> 'active';
+
+ALTER TABLE reservation
+DROP INDEX idx_reservation_schedule_status;
+
+ALTER TABLE reservation
+ADD UNIQUE INDEX unique_user_schedule (user_id, schedule_id),
+DROP INDEX unique_active_user_schedule;
+
+ALTER TABLE reservation
+DROP FOREIGN KEY fk_reservation_changed_by,
+DROP FOREIGN KEY fk_reservation_moved_from,
+DROP COLUMN active_marker,
+DROP COLUMN moved_from_id,
+DROP COLUMN changed_by,
+DROP COLUMN updated_at,
+DROP COLUMN created_at,
+DROP COLUMN status;
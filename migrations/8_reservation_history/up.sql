@@ -0,0 +1,18 @@
+ALTER TABLE reservation
+ADD status VARCHAR(16) NOT NULL DEFAULT 'active',
+ADD created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+ADD updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+ADD changed_by INT NULL,
+ADD moved_from_id INT NULL,
+ADD active_marker TINYINT GENERATED ALWAYS AS (IF(status = 'active', 1, NULL)) STORED,
+ADD CONSTRAINT fk_reservation_changed_by FOREIGN KEY (changed_by) REFERENCES users(id),
+ADD CONSTRAINT fk_reservation_moved_from FOREIGN KEY (moved_from_id) REFERENCES reservation(id);
+
+-- Cancelled and moved reservations are kept, so only active ones have to be unique per user and
+-- schedule. NULL markers never collide in a unique index.
+ALTER TABLE reservation
+ADD UNIQUE INDEX unique_active_user_schedule (user_id, schedule_id, active_marker),
+DROP INDEX unique_user_schedule;
+
+ALTER TABLE reservation
+ADD INDEX idx_reservation_schedule_status (schedule_id, status);
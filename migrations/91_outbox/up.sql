@@ -0,0 +1,7 @@
+CREATE TABLE outbox (
+                        id BIGINT AUTO_INCREMENT PRIMARY KEY,
+                        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                        event_type VARCHAR(64) NOT NULL,
+                        payload TEXT NOT NULL,
+                        INDEX idx_outbox_created_at (created_at)
+);
@@ -0,0 +1,4 @@
+UPDATE movies SET poster = '' WHERE poster IS NULL;
+
+ALTER TABLE movies
+MODIFY poster VARCHAR(255) NOT NULL;
@@ -0,0 +1,3 @@
+-- Movies imported or added without a poster show a placeholder instead.
+ALTER TABLE movies
+MODIFY poster VARCHAR(255) NULL;
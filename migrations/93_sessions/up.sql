@@ -0,0 +1,8 @@
+-- Sessions used to live in each server's memory; keeping them here lets every server see them and
+-- lets operators purge expired ones.
+CREATE TABLE sessions (
+                          id VARCHAR(32) PRIMARY KEY,
+                          data TEXT NOT NULL,
+                          expiry_date DATETIME NOT NULL,
+                          INDEX idx_sessions_expiry_date (expiry_date)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE schedule
+ADD cancelled_at DATETIME NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE movies
+DROP INDEX unique_movie_external_id,
+DROP COLUMN external_id;
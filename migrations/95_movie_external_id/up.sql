@@ -0,0 +1,9 @@
+ALTER TABLE movies
+ADD external_id VARCHAR(64) NULL;
+
+-- Movies added before external ids existed get one derived from their id.
+UPDATE movies SET external_id = CONCAT('movie-', id);
+
+ALTER TABLE movies
+MODIFY external_id VARCHAR(64) NOT NULL,
+ADD UNIQUE INDEX unique_movie_external_id (external_id);
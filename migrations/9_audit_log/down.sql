@@ -0,0 +1,4 @@
+DROP TABLE audit_log;
+
+ALTER TABLE users
+DROP COLUMN is_staff;
@@ -0,0 +1,17 @@
+ALTER TABLE users
+ADD is_staff BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Append-only: the application only ever inserts into and reads from this table.
+CREATE TABLE audit_log (
+                           id BIGINT AUTO_INCREMENT PRIMARY KEY,
+                           created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                           user_id INT NULL,
+                           action VARCHAR(32) NOT NULL,
+                           target VARCHAR(64) NULL,
+                           ip_address VARCHAR(45) NULL,
+                           before_data TEXT NULL,
+                           after_data TEXT NULL,
+                           CONSTRAINT fk_audit_log_user FOREIGN KEY (user_id) REFERENCES users(id),
+                           INDEX idx_audit_log_user (user_id, id),
+                           INDEX idx_audit_log_action (action, id)
+);
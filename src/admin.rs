@@ -0,0 +1,144 @@
+//! Operations run by operators through `cinema-admin` rather than by users of the site.
+
+use chrono::Local;
+use diesel::prelude::*;
+use diesel::MysqlConnection;
+use serde::Serialize;
+
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::events::{self, DomainEvent};
+use crate::models::ReservationStatus;
+use crate::schema::{reservation, schedule, users};
+
+/// A staff account created or promoted by an operator.
+#[derive(Debug, Clone, Serialize)]
+pub struct StaffAccount {
+    pub user_id: i32,
+    pub email: String,
+    /// False if an existing user was promoted; their password is left as it was.
+    pub created: bool,
+}
+
+/// Makes `email` a staff account, creating it with `password_hash` if it does not exist yet.
+#[tracing::instrument(skip(conn, password_hash, audit))]
+pub fn grant_staff(
+    conn: &mut MysqlConnection,
+    email: &str,
+    password_hash: &str,
+    audit: &AuditContext,
+) -> QueryResult<StaffAccount> {
+    conn.transaction(|conn| {
+        let existing = users::table
+            .filter(users::email.eq(email))
+            .select(users::id)
+            .first::<i32>(conn)
+            .optional()?;
+
+        let (user_id, created) = match existing {
+            Some(user_id) => {
+                diesel::update(users::table.find(user_id))
+                    .set(users::is_staff.eq(true))
+                    .execute(conn)?;
+                (user_id, false)
+            }
+            None => {
+                diesel::insert_into(users::table)
+                    .values((
+                        users::email.eq(email),
+                        users::password.eq(password_hash),
+                        users::is_staff.eq(true),
+                    ))
+                    .execute(conn)?;
+                let user_id = users::table
+                    .filter(users::email.eq(email))
+                    .select(users::id)
+                    .first::<i32>(conn)?;
+                events::emit(conn, &DomainEvent::UserRegistered { user_id, email: email.to_string() })?;
+                (user_id, true)
+            }
+        };
+
+        audit::record(
+            conn,
+            audit,
+            AuditAction::StaffGranted,
+            Some(format!("user:{user_id}")),
+            None,
+            Some(serde_json::json!({ "email": email, "created": created })),
+        )?;
+        Ok(StaffAccount { user_id, email: email.to_string(), created })
+    })
+}
+
+/// A reservation cancelled along with its screening, and who held it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CancelledHolder {
+    pub reservation_id: i32,
+    pub user_id: i32,
+    pub email: String,
+}
+
+/// Cancels screening `schedule_id` together with its active reservations, so that it can no
+/// longer be booked. Each holder is notified through a `ReservationCancelled` event, and the
+/// screening itself through `ScheduleChanged`. Returns the holders, none if the screening had
+/// already been cancelled.
+#[tracing::instrument(skip(conn, audit))]
+pub fn cancel_screening(
+    conn: &mut MysqlConnection,
+    schedule_id: i32,
+    audit: &AuditContext,
+) -> QueryResult<Vec<CancelledHolder>> {
+    conn.transaction(|conn| {
+        let now = Local::now().naive_local();
+        let cancelled = diesel::update(
+            schedule::table
+                .find(schedule_id)
+                .filter(schedule::cancelled_at.is_null()),
+        )
+        .set(schedule::cancelled_at.eq(now))
+        .execute(conn)?;
+        if cancelled == 0 {
+            // Fails with `NotFound` for a screening that does not exist.
+            schedule::table.find(schedule_id).select(schedule::id).first::<i32>(conn)?;
+            return Ok(Vec::new());
+        }
+
+        let holders: Vec<CancelledHolder> = reservation::table
+            .inner_join(users::table)
+            .filter(reservation::schedule_id.eq(schedule_id))
+            .filter(reservation::status.eq(ReservationStatus::Active))
+            .select((reservation::id, users::id, users::email))
+            .load::<(i32, i32, String)>(conn)?
+            .into_iter()
+            .map(|(reservation_id, user_id, email)| CancelledHolder { reservation_id, user_id, email })
+            .collect();
+        let reservation_ids: Vec<i32> = holders.iter().map(|holder| holder.reservation_id).collect();
+
+        diesel::update(reservation::table.filter(reservation::id.eq_any(&reservation_ids)))
+            .set((
+                reservation::status.eq(ReservationStatus::Cancelled),
+                reservation::changed_by.eq(audit.user_id),
+            ))
+            .execute(conn)?;
+
+        audit::record(
+            conn,
+            audit,
+            AuditAction::ScreeningCancelled,
+            Some(format!("schedule:{schedule_id}")),
+            None,
+            Some(serde_json::json!({ "cancelled_reservations": reservation_ids })),
+        )?;
+        for holder in &holders {
+            events::emit(conn, &DomainEvent::ReservationCancelled {
+                reservation_id: holder.reservation_id,
+                user_id: holder.user_id,
+                schedule_id,
+                cancelled_by: audit.user_id,
+            })?;
+        }
+        events::emit(conn, &DomainEvent::ScheduleChanged { schedule_id })?;
+
+        Ok(holders)
+    })
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use diesel::mysql::Mysql;
+use diesel::prelude::*;
+use diesel::MysqlConnection;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::models::{AuditEntry, NewAuditEntry, Reservation};
+use crate::schema::{audit_log, users};
+
+/// Kind of an audited operation, stored in `audit_log.action` by its snake_case name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Register,
+    ReservationCreated,
+    ReservationMoved,
+    ReservationCancelled,
+    ReservationsBulkCancelled,
+    StaffGranted,
+    MoviesImported,
+    ScreeningCancelled,
+    PosterChanged,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 11] = [
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::Register,
+        AuditAction::ReservationCreated,
+        AuditAction::ReservationMoved,
+        AuditAction::ReservationCancelled,
+        AuditAction::ReservationsBulkCancelled,
+        AuditAction::StaffGranted,
+        AuditAction::MoviesImported,
+        AuditAction::ScreeningCancelled,
+        AuditAction::PosterChanged,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::Register => "register",
+            AuditAction::ReservationCreated => "reservation_created",
+            AuditAction::ReservationMoved => "reservation_moved",
+            AuditAction::ReservationCancelled => "reservation_cancelled",
+            AuditAction::ReservationsBulkCancelled => "reservations_bulk_cancelled",
+            AuditAction::StaffGranted => "staff_granted",
+            AuditAction::MoviesImported => "movies_imported",
+            AuditAction::ScreeningCancelled => "screening_cancelled",
+            AuditAction::PosterChanged => "poster_changed",
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AuditAction::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("unknown audit action: {s}"))
+    }
+}
+
+/// Who performs an operation and from where, attached to every audit entry it produces.
+#[derive(Debug, Clone, Default)]
+pub struct AuditContext {
+    /// Signed-in user, or the account a login was attempted for.
+    pub user_id: Option<i32>,
+    pub ip_address: Option<String>,
+}
+
+impl AuditContext {
+    pub fn new(user_id: Option<i32>, ip_address: Option<String>) -> Self {
+        Self { user_id, ip_address }
+    }
+}
+
+/// Appends an entry to the audit log. Callers changing data should do so in the same
+/// transaction, so that the entry exists exactly when the change does.
+#[tracing::instrument(skip_all)]
+pub fn record(
+    conn: &mut MysqlConnection,
+    context: &AuditContext,
+    action: AuditAction,
+    target: Option<String>,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> QueryResult<()> {
+    diesel::insert_into(audit_log::table)
+        .values(&NewAuditEntry {
+            user_id: context.user_id,
+            action: action.as_str(),
+            target,
+            ip_address: context.ip_address.as_deref(),
+            before_data: before.map(|value| value.to_string()),
+            after_data: after.map(|value| value.to_string()),
+        })
+        .execute(conn)
+        .map(|_| ())
+}
+
+pub fn movie_target(movie_id: i32) -> String {
+    format!("movie:{movie_id}")
+}
+
+pub fn reservation_target(reservation_id: i32) -> String {
+    format!("reservation:{reservation_id}")
+}
+
+/// The fields of a reservation worth keeping in the before/after data of an audit entry.
+pub fn reservation_snapshot(reservation: &Reservation) -> Value {
+    json!({
+        "id": reservation.id,
+        "user_id": reservation.user_id,
+        "schedule_id": reservation.schedule_id,
+        "status": reservation.status.as_str(),
+        "moved_from_id": reservation.moved_from_id,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter<'a> {
+    /// Email of the user the entries belong to.
+    pub user_email: Option<&'a str>,
+    pub action: Option<AuditAction>,
+}
+
+#[derive(Debug)]
+pub struct AuditLogRow {
+    pub entry: AuditEntry,
+    pub user_email: Option<String>,
+}
+
+fn filtered_audit_log<'a>(filter: &AuditFilter<'a>) -> audit_log::BoxedQuery<'a, Mysql> {
+    let mut query = audit_log::table.into_boxed();
+
+    if let Some(user_email) = filter.user_email {
+        query = query.filter(
+            audit_log::user_id.eq_any(
+                users::table
+                    .filter(users::email.eq(user_email))
+                    .select(users::id.nullable()),
+            ),
+        );
+    }
+    if let Some(action) = filter.action {
+        query = query.filter(audit_log::action.eq(action.as_str()));
+    }
+
+    query
+}
+
+/// Returns one page of audit entries matching `filter`, newest first, together with the total
+/// number of matches.
+#[tracing::instrument(skip_all)]
+pub fn search_audit_log(
+    conn: &mut MysqlConnection,
+    filter: &AuditFilter,
+    limit: i64,
+    offset: i64,
+) -> QueryResult<(Vec<AuditLogRow>, i64)> {
+    let total = filtered_audit_log(filter).count().get_result::<i64>(conn)?;
+
+    let entries = filtered_audit_log(filter)
+        .order(audit_log::id.desc())
+        .limit(limit)
+        .offset(offset)
+        .select(AuditEntry::as_select())
+        .load::<AuditEntry>(conn)?;
+
+    let user_ids: Vec<i32> = entries.iter().filter_map(|entry| entry.user_id).collect();
+    let emails: HashMap<i32, String> = users::table
+        .filter(users::id.eq_any(user_ids))
+        .select((users::id, users::email))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    let rows = entries
+        .into_iter()
+        .map(|entry| AuditLogRow {
+            user_email: entry.user_id.and_then(|id| emails.get(&id).cloned()),
+            entry,
+        })
+        .collect();
+
+    Ok((rows, total))
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use askama::Template;
+use diesel::MysqlConnection;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::db::{self, MysqlPool, ScreeningFilter};
+use crate::event_bus::EventBus;
+use crate::shutdown::Shutdown;
+use crate::templates_structs::ScheduleOptionLabelTemplate;
+
+/// New seat availability of a schedule, with its option label already rendered.
+#[derive(Debug, Clone)]
+pub struct AvailabilityUpdate {
+    pub schedule_id: i32,
+    pub label: String,
+}
+
+/// Fans out availability changes to the clients connected to this replica.
+#[derive(Clone)]
+pub struct AvailabilityFeed {
+    sender: broadcast::Sender<AvailabilityUpdate>,
+}
+
+impl Default for AvailabilityFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AvailabilityFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(1024);
+        AvailabilityFeed { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AvailabilityUpdate> {
+        self.sender.subscribe()
+    }
+
+    fn publish(&self, update: AvailabilityUpdate) {
+        // Nobody may be listening, which is fine.
+        let _ = self.sender.send(update);
+    }
+}
+
+fn load_updates(conn: &mut MysqlConnection, schedule_ids: Vec<i32>) -> Result<Vec<AvailabilityUpdate>, String> {
+    let filter = ScreeningFilter {
+        schedule_ids: Some(schedule_ids),
+        ..Default::default()
+    };
+
+    db::get_screenings(conn, &filter)
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|schedule_info| {
+            let label = ScheduleOptionLabelTemplate { schedule_info }
+                .render()
+                .map_err(|e| e.to_string())?;
+            Ok(AvailabilityUpdate {
+                schedule_id: schedule_info.schedule.id,
+                label: label.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Recomputes the availability of the schedules touched by domain events on `bus` and publishes
+/// it to `feed`. Events that arrive together are handled with one query. Stops on shutdown.
+pub fn spawn_availability_updater(
+    pool: Arc<MysqlPool>,
+    bus: &EventBus,
+    feed: AvailabilityFeed,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    let mut events = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let received = tokio::select! {
+                received = events.recv() => received,
+                _ = shutdown.triggered() => break,
+            };
+            let mut schedule_ids = match received {
+                Ok(event) => event.event.affected_schedules(),
+                // Missed events may have touched any schedule; clients catch up on their next page load.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            while let Ok(event) = events.try_recv() {
+                schedule_ids.extend(event.event.affected_schedules());
+            }
+            schedule_ids.sort_unstable();
+            schedule_ids.dedup();
+            if schedule_ids.is_empty() {
+                continue;
+            }
+
+            let updates = db::run(&pool, move |conn| load_updates(conn, schedule_ids)).await;
+
+            match updates {
+                Ok(Ok(updates)) => updates.into_iter().for_each(|update| feed.publish(update)),
+                Ok(Err(e)) => tracing::error!("Failed to load seat availability: {e}"),
+                Err(e) => tracing::error!("Failed to run seat availability update: {e}"),
+            }
+        }
+    })
+}
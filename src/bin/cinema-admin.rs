@@ -0,0 +1,242 @@
+//! Operator tasks that would otherwise need hand-written SQL. Every command runs in one database
+//! transaction; `--dry-run` rolls it back after reporting what it would have done.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use bcrypt::{hash, DEFAULT_COST};
+use chrono::Local;
+use clap::{Parser, Subcommand};
+use diesel::prelude::*;
+use diesel::MysqlConnection;
+use dotenvy::dotenv;
+use serde::Serialize;
+
+use Cinema::admin::{self, CancelledHolder, StaffAccount};
+use Cinema::audit::AuditContext;
+use Cinema::catalogue::{self, CatalogueError, CatalogueFormat, ImportSummary};
+use Cinema::sessions;
+
+#[derive(Debug, Parser)]
+#[command(name = "cinema-admin", about = "Administrative tasks for the cinema database")]
+struct Cli {
+    /// Report what would change without changing anything.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Print the result as a JSON object instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a staff account, or make an existing user staff. The password of a new account is
+    /// read from the first line of stdin.
+    CreateAdmin {
+        email: String,
+    },
+    /// Add or update the movies in a CSV or JSON catalogue file, matching them on `external_id`.
+    /// Nothing is changed if any row is invalid. Use `-` to read stdin.
+    ImportMovies {
+        file: PathBuf,
+        /// Format of the file; taken from its extension by default.
+        #[arg(long)]
+        format: Option<CatalogueFormat>,
+    },
+    /// Write the whole movie catalogue to a CSV or JSON file. Use `-` to write to stdout.
+    ExportMovies {
+        file: PathBuf,
+        /// Format of the file; taken from its extension by default.
+        #[arg(long)]
+        format: Option<CatalogueFormat>,
+    },
+    /// Cancel a screening and all its reservations, notifying their holders.
+    CancelScreening {
+        schedule_id: i32,
+    },
+    /// Delete expired sessions.
+    PurgeSessions,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Outcome {
+    CreateAdmin(StaffAccount),
+    ImportMovies(ImportSummary),
+    ExportMovies { exported: usize },
+    CancelScreening { schedule_id: i32, cancelled_reservations: Vec<CancelledHolder> },
+    PurgeSessions { deleted: usize },
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    dry_run: bool,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dry_run {
+            write!(f, "[dry run] ")?;
+        }
+        match &self.outcome {
+            Outcome::CreateAdmin(account) if account.created => {
+                write!(f, "Created staff account {} (user {})", account.email, account.user_id)
+            }
+            Outcome::CreateAdmin(account) => {
+                write!(f, "Made {} (user {}) staff", account.email, account.user_id)
+            }
+            Outcome::ImportMovies(summary) => write!(
+                f,
+                "Imported {} new movie(s), updated {}, left {} unchanged",
+                summary.inserted, summary.updated, summary.unchanged
+            ),
+            Outcome::ExportMovies { exported } => write!(f, "Exported {exported} movie(s)"),
+            Outcome::CancelScreening { schedule_id, cancelled_reservations } => {
+                write!(
+                    f,
+                    "Cancelled screening {schedule_id} and {} reservation(s)",
+                    cancelled_reservations.len()
+                )?;
+                for holder in cancelled_reservations {
+                    write!(f, "\n  reservation {} of {} (user {})", holder.reservation_id, holder.email, holder.user_id)?;
+                }
+                Ok(())
+            }
+            Outcome::PurgeSessions { deleted } => write!(f, "Deleted {deleted} expired session(s)"),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+enum Error {
+    /// DATABASE_URL must be set
+    MissingDatabaseUrl,
+    /// could not connect to the database: {0}
+    Connect(#[from] diesel::ConnectionError),
+    /// database error: {0}
+    Database(#[from] diesel::result::Error),
+    /// could not read {0}: {1}
+    Read(String, #[source] io::Error),
+    /// could not write {0}: {1}
+    Write(String, #[source] io::Error),
+    /// cannot tell the format of {0}; pass --format csv or --format json
+    UnknownFormat(String),
+    /// {0}
+    Catalogue(#[from] CatalogueError),
+    /// a password for the new account is expected on stdin
+    MissingPassword,
+    /// could not hash the password: {0}
+    Hash(#[from] bcrypt::BcryptError),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // An export to stdout owns it; the report then goes to stderr so that it can be piped.
+    let exporting_to_stdout = matches!(&cli.command, Command::ExportMovies { file, .. } if file.as_os_str() == "-");
+    let print = |line: String| {
+        if exporting_to_stdout {
+            eprintln!("{line}");
+        } else {
+            println!("{line}");
+        }
+    };
+    match run(&cli) {
+        Ok(report) => {
+            if cli.json {
+                print(serde_json::to_string(&report).expect("reports serialize"));
+            } else {
+                print(report.to_string());
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            if cli.json {
+                let mut error = serde_json::json!({ "error": e.to_string() });
+                if let Error::Catalogue(CatalogueError::InvalidRows(rows)) = &e {
+                    error["rows"] = serde_json::to_value(rows).expect("row errors serialize");
+                }
+                print(error.to_string());
+            } else {
+                eprintln!("cinema-admin: {e}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<Report, Error> {
+    dotenv().ok();
+    let url = std::env::var("DATABASE_URL").map_err(|_| Error::MissingDatabaseUrl)?;
+    let conn = &mut MysqlConnection::establish(&url)?;
+    if cli.dry_run {
+        // Never committed: everything below is rolled back when the connection closes.
+        conn.begin_test_transaction()?;
+    }
+    // Operators act outside of any user account or client address.
+    let audit = AuditContext::default();
+
+    let outcome = match &cli.command {
+        Command::CreateAdmin { email } => {
+            let password = read_password()?;
+            let password_hash = hash(password, DEFAULT_COST)?;
+            Outcome::CreateAdmin(admin::grant_staff(conn, email, &password_hash, &audit)?)
+        }
+        Command::ImportMovies { file, format } => {
+            let format = catalogue_format(file, *format)?;
+            let movies = if file.as_os_str() == "-" {
+                catalogue::parse(format, io::stdin().lock())?
+            } else {
+                let input = fs::File::open(file).map_err(|e| Error::Read(file.display().to_string(), e))?;
+                catalogue::parse(format, io::BufReader::new(input))?
+            };
+            Outcome::ImportMovies(catalogue::import(conn, &movies, &audit)?)
+        }
+        Command::ExportMovies { file, format } => {
+            let format = catalogue_format(file, *format)?;
+            let movies = catalogue::export(conn)?;
+            if file.as_os_str() == "-" {
+                catalogue::write(format, &movies, io::stdout().lock())?;
+            } else {
+                let output = fs::File::create(file).map_err(|e| Error::Write(file.display().to_string(), e))?;
+                catalogue::write(format, &movies, io::BufWriter::new(output))?;
+            }
+            Outcome::ExportMovies { exported: movies.len() }
+        }
+        Command::CancelScreening { schedule_id } => Outcome::CancelScreening {
+            schedule_id: *schedule_id,
+            cancelled_reservations: admin::cancel_screening(conn, *schedule_id, &audit)?,
+        },
+        Command::PurgeSessions => Outcome::PurgeSessions {
+            deleted: sessions::delete_expired_sessions(conn, Local::now().naive_local())?,
+        },
+    };
+
+    Ok(Report { dry_run: cli.dry_run, outcome })
+}
+
+fn read_password() -> Result<String, Error> {
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| Error::Read("stdin".to_string(), e))?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(Error::MissingPassword);
+    }
+    Ok(password.to_string())
+}
+
+/// `format`, or the format matching the extension of `file`. Stdin and stdout have no extension.
+fn catalogue_format(file: &Path, format: Option<CatalogueFormat>) -> Result<CatalogueFormat, Error> {
+    format
+        .or_else(|| CatalogueFormat::from_path(file))
+        .ok_or_else(|| Error::UnknownFormat(file.display().to_string()))
+}
@@ -0,0 +1,536 @@
+//! Load generator for a running server. Simulated users repeat one scenario concurrently for a
+//! fixed time while the database is checked for screenings booked beyond the capacity of their
+//! room. Prints a JSON report of outcomes and latency percentiles, and fails if any screening was
+//! ever overbooked.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use clap::{Parser, ValueEnum};
+use diesel::prelude::*;
+use diesel::MysqlConnection;
+use dotenvy::dotenv;
+use rand::rngs::SmallRng;
+use rand::seq::IndexedRandom;
+use rand::SeedableRng;
+use reqwest::header::{HeaderName, COOKIE, SET_COOKIE};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::Serialize;
+use tokio::task::{JoinError, JoinSet};
+use tokio::time::Instant;
+
+use Cinema::db::{self, OverbookedScreening};
+use Cinema::handlers::reservations::RESERVATIONS_PER_PAGE;
+
+const PASSWORD: &str = "load-test-password";
+const CAPACITY_EXCEEDED: &str = "Room capacity exceeded for schedule";
+const ALREADY_RESERVED: &str = "This user already has a reservation for the selected schedule";
+const HX_REDIRECT: HeaderName = HeaderName::from_static("hx-redirect");
+
+#[derive(Debug, Parser)]
+#[command(name = "cinema-load", about = "Load test a running cinema server")]
+struct Cli {
+    scenario: Scenario,
+    /// Address of the server under test.
+    #[arg(long, default_value = "http://localhost:8080")]
+    base_url: String,
+    /// Number of simulated users sending requests at the same time.
+    #[arg(long, default_value_t = 10)]
+    clients: usize,
+    /// How long to keep sending requests, not counting signing the users in.
+    #[arg(long, default_value_t = 30)]
+    duration_secs: u64,
+    /// How often to look for overbooked screenings while the load runs.
+    #[arg(long, default_value_t = 500)]
+    check_interval_ms: u64,
+    /// Write the report to this file instead of stdout.
+    #[arg(long)]
+    report: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Scenario {
+    /// Anonymous users list the movies as fast as they can.
+    RapidSingle,
+    /// Signed-in users open the movies, their reservations or the booking form at random.
+    RandomConcurrent,
+    /// Signed-in users book random screenings until every seat is taken.
+    ImmediateOccupancy,
+    /// Each signed-in user books one screening and cancels the reservation, over and over.
+    ConstantCancellations,
+    /// Signed-in users book a page of screenings and cancel them all in one request, over and
+    /// over.
+    BulkDelete,
+}
+
+impl Scenario {
+    fn signs_in(self) -> bool {
+        self != Scenario::RapidSingle
+    }
+
+    fn books(self) -> bool {
+        matches!(self, Scenario::ImmediateOccupancy | Scenario::ConstantCancellations | Scenario::BulkDelete)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Operation {
+    ListMovies,
+    ListReservations,
+    ShowBookingForm,
+    Book,
+    Cancel,
+    BulkDelete,
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+enum Error {
+    /// DATABASE_URL must be set to check room capacities
+    MissingDatabaseUrl,
+    /// could not connect to the database: {0}
+    Connect(#[from] diesel::ConnectionError),
+    /// database error: {0}
+    Database(#[from] diesel::result::Error),
+    /// request failed: {0}
+    Http(#[from] reqwest::Error),
+    /// could not register {0}
+    Register(String),
+    /// could not sign in as {0}
+    Login(String),
+    /// the booking form lists no screenings
+    NoScreenings,
+    /// could not write {0}: {1}
+    Write(String, #[source] std::io::Error),
+    /// a task panicked: {0}
+    Task(#[from] JoinError),
+}
+
+/// A response read to the end.
+struct Reply {
+    status: StatusCode,
+    body: String,
+}
+
+impl Reply {
+    fn outcome(&self) -> String {
+        if !self.status.is_success() {
+            format!("http_{}", self.status.as_u16())
+        } else if self.body.contains(CAPACITY_EXCEEDED) {
+            "capacity_exceeded".to_string()
+        } else if self.body.contains(ALREADY_RESERVED) {
+            "already_reserved".to_string()
+        } else {
+            "ok".to_string()
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        self.outcome() == "ok"
+    }
+}
+
+/// One simulated user, signed in or not, with its own session cookie.
+struct Client {
+    http: reqwest::Client,
+    base_url: Arc<str>,
+    cookie: Option<String>,
+}
+
+impl Client {
+    fn anonymous(http: reqwest::Client, base_url: Arc<str>) -> Self {
+        Client { http, base_url, cookie: None }
+    }
+
+    /// Registers a new account for `email` and signs in with it.
+    async fn sign_up(http: reqwest::Client, base_url: Arc<str>, email: String) -> Result<Self, Error> {
+        let mut client = Client::anonymous(http, base_url);
+        let form = [("email", email.as_str()), ("password", PASSWORD), ("password_confirmation", PASSWORD)];
+        let response = client.http.post(client.url("/register")).form(&form).send().await?;
+        // Both forms answer 200; success is told apart by the redirect htmx is asked to follow.
+        if !response.headers().contains_key(HX_REDIRECT) {
+            return Err(Error::Register(email));
+        }
+
+        let form = [("email", email.as_str()), ("password", PASSWORD)];
+        let response = client.http.post(client.url("/login")).form(&form).send().await?;
+        client.keep_cookie(&response);
+        if !response.headers().contains_key(HX_REDIRECT) || client.cookie.is_none() {
+            return Err(Error::Login(email));
+        }
+        Ok(client)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    fn keep_cookie(&mut self, response: &reqwest::Response) {
+        let set_cookie = response.headers().get(SET_COOKIE).and_then(|value| value.to_str().ok());
+        if let Some(cookie) = set_cookie.and_then(|value| value.split(';').next()) {
+            self.cookie = Some(cookie.to_string());
+        }
+    }
+
+    /// Sends `request` and records how long the whole response took and what it said under
+    /// `operation`. A request that got no response is recorded without a latency.
+    async fn call(&mut self, stats: &mut Stats, operation: Operation, request: RequestBuilder) -> Option<Reply> {
+        let request = match &self.cookie {
+            Some(cookie) => request.header(COOKIE, cookie),
+            None => request,
+        };
+        let started = Instant::now();
+        let reply = async {
+            let response = request.send().await?;
+            self.keep_cookie(&response);
+            let status = response.status();
+            Ok::<_, reqwest::Error>(Reply { status, body: response.text().await? })
+        }
+        .await;
+
+        match reply {
+            Ok(reply) => {
+                stats.record(operation, &reply.outcome(), Some(started.elapsed()));
+                Some(reply)
+            }
+            Err(_) => {
+                stats.record(operation, "transport_error", None);
+                None
+            }
+        }
+    }
+
+    async fn get(&mut self, stats: &mut Stats, operation: Operation, path: &str) -> Option<Reply> {
+        let request = self.http.get(self.url(path));
+        self.call(stats, operation, request).await
+    }
+
+    async fn book(&mut self, stats: &mut Stats, schedule_id: i32) -> Option<Reply> {
+        let request = self.http.post(self.url("/reservations")).form(&[("schedule_id", schedule_id)]);
+        self.call(stats, Operation::Book, request).await
+    }
+
+    /// The ids of the user's upcoming reservations on the first page of the list.
+    async fn reservation_ids(&mut self, stats: &mut Stats) -> Vec<i32> {
+        match self.get(stats, Operation::ListReservations, "/reservations").await {
+            Some(reply) if reply.status.is_success() => ids_after(&reply.body, "hx-delete=\"/reservations/"),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The numbers following each occurrence of `marker` in `html`.
+fn ids_after(html: &str, marker: &str) -> Vec<i32> {
+    html.split(marker)
+        .skip(1)
+        .filter_map(|rest| {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            rest[..digits].parse().ok()
+        })
+        .collect()
+}
+
+/// The screenings offered by the booking form.
+async fn bookable_schedules(client: &mut Client) -> Result<Vec<i32>, Error> {
+    let mut stats = Stats::default();
+    let reply = client
+        .get(&mut stats, Operation::ShowBookingForm, "/reservations/new")
+        .await
+        .filter(|reply| reply.status.is_success())
+        .ok_or(Error::NoScreenings)?;
+    let schedules = ids_after(&reply.body, "<option value=\"");
+    if schedules.is_empty() {
+        return Err(Error::NoScreenings);
+    }
+    Ok(schedules)
+}
+
+/// Repeats one round of `scenario` until `deadline`.
+async fn drive(scenario: Scenario, mut client: Client, schedules: Arc<[i32]>, deadline: Instant) -> Stats {
+    let mut stats = Stats::default();
+    let mut rng = SmallRng::from_os_rng();
+    // Cancelling users keep coming back to the same screening, so that they compete for its seats.
+    let favourite = schedules.choose(&mut rng).copied();
+
+    while Instant::now() < deadline {
+        match scenario {
+            Scenario::RapidSingle => {
+                client.get(&mut stats, Operation::ListMovies, "/movies").await;
+            }
+            Scenario::RandomConcurrent => {
+                let pages = [
+                    (Operation::ListMovies, "/movies"),
+                    (Operation::ListReservations, "/reservations"),
+                    (Operation::ShowBookingForm, "/reservations/new"),
+                ];
+                let (operation, path) = *pages.choose(&mut rng).expect("there are pages");
+                client.get(&mut stats, operation, path).await;
+            }
+            Scenario::ImmediateOccupancy => {
+                let schedule_id = *schedules.choose(&mut rng).expect("there are screenings");
+                client.book(&mut stats, schedule_id).await;
+            }
+            Scenario::ConstantCancellations => {
+                let schedule_id = favourite.expect("there are screenings");
+                if client.book(&mut stats, schedule_id).await.is_some_and(|reply| reply.is_ok()) {
+                    for id in client.reservation_ids(&mut stats).await {
+                        let request = client.http.delete(client.url(&format!("/reservations/{id}")));
+                        client.call(&mut stats, Operation::Cancel, request).await;
+                    }
+                }
+            }
+            Scenario::BulkDelete => {
+                let batch = schedules.choose_multiple(&mut rng, RESERVATIONS_PER_PAGE as usize);
+                for &schedule_id in batch {
+                    client.book(&mut stats, schedule_id).await;
+                }
+                let ids = client.reservation_ids(&mut stats).await;
+                if !ids.is_empty() {
+                    let ids = ids.iter().map(i32::to_string).collect::<Vec<_>>().join(",");
+                    let request = client.http.post(client.url("/reservations/bulk_delete")).form(&[("reservation_ids", ids)]);
+                    client.call(&mut stats, Operation::BulkDelete, request).await;
+                }
+            }
+        }
+    }
+    stats
+}
+
+/// What one or more simulated users saw.
+#[derive(Debug, Default)]
+struct Stats {
+    operations: BTreeMap<Operation, Samples>,
+}
+
+#[derive(Debug, Default)]
+struct Samples {
+    latencies: Vec<Duration>,
+    outcomes: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    fn record(&mut self, operation: Operation, outcome: &str, latency: Option<Duration>) {
+        let samples = self.operations.entry(operation).or_default();
+        *samples.outcomes.entry(outcome.to_string()).or_default() += 1;
+        samples.latencies.extend(latency);
+    }
+
+    fn merge(&mut self, other: Stats) {
+        for (operation, theirs) in other.operations {
+            let ours = self.operations.entry(operation).or_default();
+            ours.latencies.extend(theirs.latencies);
+            for (outcome, count) in theirs.outcomes {
+                *ours.outcomes.entry(outcome).or_default() += count;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LatencySummary {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl LatencySummary {
+    /// Nearest-rank percentiles of `latencies`, in milliseconds.
+    fn of(latencies: &mut [Duration]) -> Self {
+        latencies.sort_unstable();
+        let percentile = |p: usize| {
+            let rank = (p * latencies.len()).div_ceil(100).max(1);
+            latencies.get(rank - 1).map_or(0.0, |latency| latency.as_secs_f64() * 1000.0)
+        };
+        LatencySummary { p50: percentile(50), p90: percentile(90), p99: percentile(99), max: percentile(100) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OperationReport {
+    requests: u64,
+    outcomes: BTreeMap<String, u64>,
+    latency_ms: LatencySummary,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CapacityCheck {
+    checks: u64,
+    /// Every screening ever seen overbooked, with the most reservations it was seen holding.
+    overbooked: Vec<OverbookedScreening>,
+}
+
+impl CapacityCheck {
+    fn record(&mut self, found: Vec<OverbookedScreening>) {
+        self.checks += 1;
+        for screening in found {
+            match self.overbooked.iter_mut().find(|seen| seen.schedule_id == screening.schedule_id) {
+                Some(seen) => seen.reserved = seen.reserved.max(screening.reserved),
+                None => self.overbooked.push(screening),
+            }
+        }
+    }
+}
+
+/// Looks for overbooked screenings every `interval` until `stop` is set, and once more after.
+fn watch_capacity(
+    conn: &mut MysqlConnection,
+    interval: Duration,
+    stop: &AtomicBool,
+) -> Result<CapacityCheck, diesel::result::Error> {
+    let mut check = CapacityCheck::default();
+    loop {
+        let stopping = stop.load(Ordering::Relaxed);
+        check.record(db::get_overbooked_screenings(conn)?);
+        if stopping {
+            return Ok(check);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Tells the capacity watcher to finish when dropped, so that it also stops when the run ends
+/// early. The runtime would otherwise wait for it forever on shutdown.
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    scenario: Scenario,
+    base_url: String,
+    clients: usize,
+    duration_secs: f64,
+    requests: u64,
+    requests_per_second: f64,
+    latency_ms: LatencySummary,
+    operations: BTreeMap<Operation, OperationReport>,
+    capacity: CapacityCheck,
+}
+
+impl Report {
+    fn new(cli: &Cli, elapsed: Duration, stats: Stats, capacity: CapacityCheck) -> Self {
+        let mut all_latencies = Vec::new();
+        let operations: BTreeMap<_, _> = stats
+            .operations
+            .into_iter()
+            .map(|(operation, mut samples)| {
+                all_latencies.extend_from_slice(&samples.latencies);
+                let report = OperationReport {
+                    requests: samples.outcomes.values().sum(),
+                    latency_ms: LatencySummary::of(&mut samples.latencies),
+                    outcomes: samples.outcomes,
+                };
+                (operation, report)
+            })
+            .collect();
+        let requests = operations.values().map(|report| report.requests).sum();
+        let duration_secs = elapsed.as_secs_f64();
+
+        Report {
+            scenario: cli.scenario,
+            base_url: cli.base_url.clone(),
+            clients: cli.clients,
+            duration_secs,
+            requests,
+            requests_per_second: if duration_secs > 0.0 { requests as f64 / duration_secs } else { 0.0 },
+            latency_ms: LatencySummary::of(&mut all_latencies),
+            operations,
+            capacity,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli).await {
+        Ok(report) if report.capacity.overbooked.is_empty() => ExitCode::SUCCESS,
+        Ok(report) => {
+            eprintln!(
+                "cinema-load: {} screening(s) held more reservations than their room has seats",
+                report.capacity.overbooked.len()
+            );
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("cinema-load: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<Report, Error> {
+    dotenv().ok();
+    let url = std::env::var("DATABASE_URL").map_err(|_| Error::MissingDatabaseUrl)?;
+    let mut conn = MysqlConnection::establish(&url)?;
+
+    let http = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+    let base_url: Arc<str> = cli.base_url.trim_end_matches('/').into();
+    let mut clients = if cli.scenario.signs_in() {
+        sign_up_all(&http, &base_url, cli.clients).await?
+    } else {
+        (0..cli.clients).map(|_| Client::anonymous(http.clone(), base_url.clone())).collect()
+    };
+    let schedules: Arc<[i32]> = match clients.first_mut() {
+        Some(client) if cli.scenario.books() => bookable_schedules(client).await?.into(),
+        _ => Arc::new([]),
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let interval = Duration::from_millis(cli.check_interval_ms);
+    let watcher = tokio::task::spawn_blocking({
+        let stop = stop.clone();
+        move || watch_capacity(&mut conn, interval, &stop)
+    });
+    let stop_watcher = StopOnDrop(stop);
+
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(cli.duration_secs);
+    let mut users = JoinSet::new();
+    for client in clients {
+        users.spawn(drive(cli.scenario, client, schedules.clone(), deadline));
+    }
+    let mut stats = Stats::default();
+    while let Some(user_stats) = users.join_next().await {
+        stats.merge(user_stats?);
+    }
+    let elapsed = started.elapsed();
+
+    drop(stop_watcher);
+    let capacity = watcher.await??;
+    let report = Report::new(cli, elapsed, stats, capacity);
+
+    let json = serde_json::to_string_pretty(&report).expect("reports serialize");
+    match &cli.report {
+        Some(path) => fs::write(path, json).map_err(|e| Error::Write(path.display().to_string(), e))?,
+        None => println!("{json}"),
+    }
+    Ok(report)
+}
+
+/// Registers and signs in `count` users with addresses unique to this run.
+async fn sign_up_all(http: &reqwest::Client, base_url: &Arc<str>, count: usize) -> Result<Vec<Client>, Error> {
+    let run_id = Utc::now().timestamp_millis();
+    let mut sign_ups = JoinSet::new();
+    for i in 0..count {
+        let email = format!("load-{run_id}-{i}@example.com");
+        sign_ups.spawn(Client::sign_up(http.clone(), base_url.clone(), email));
+    }
+    let mut clients = Vec::with_capacity(count);
+    while let Some(client) = sign_ups.join_next().await {
+        clients.push(client??);
+    }
+    Ok(clients)
+}
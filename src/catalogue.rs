@@ -0,0 +1,287 @@
+//! Bulk import and export of the movie catalogue. Movies are matched on their `external_id`, the
+//! identifier the catalogue's source uses, so that re-importing an edited file updates movies in
+//! place instead of duplicating them.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use diesel::prelude::*;
+use diesel::MysqlConnection;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::events::{self, DomainEvent};
+use crate::schema::movies;
+
+/// Oldest year a movie can be from.
+const FIRST_MOVIE_YEAR: i32 = 1888;
+const LAST_MOVIE_YEAR: i32 = 2100;
+
+/// A movie as it appears in catalogue files.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[diesel(table_name = movies)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+#[diesel(treat_none_as_null = true)]
+pub struct CatalogueMovie {
+    pub external_id: String,
+    pub title: String,
+    pub year: i32,
+    pub director: String,
+    /// Empty in a CSV file when the movie has no poster.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub poster: Option<String>,
+}
+
+impl CatalogueMovie {
+    /// This movie after importing `imported`, the row with the same external id. A row without a
+    /// poster keeps the current one, which may have been uploaded by staff.
+    pub fn updated_by(&self, imported: &CatalogueMovie) -> CatalogueMovie {
+        CatalogueMovie {
+            poster: imported.poster.clone().or_else(|| self.poster.clone()),
+            ..imported.clone()
+        }
+    }
+}
+
+fn empty_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.filter(|value| !value.is_empty()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogueFormat {
+    Csv,
+    Json,
+}
+
+impl CatalogueFormat {
+    /// The format matching the extension of `path`, if it has a known one.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for CatalogueFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "csv" => Ok(CatalogueFormat::Csv),
+            "json" => Ok(CatalogueFormat::Json),
+            other => Err(format!("unknown catalogue format {other:?}, expected csv or json")),
+        }
+    }
+}
+
+/// Why one row of a catalogue file was rejected. Rows are numbered from 1, not counting the CSV
+/// header.
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub row: usize,
+    pub external_id: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.external_id {
+            Some(external_id) => write!(f, "row {} ({external_id}): {}", self.row, self.message),
+            None => write!(f, "row {}: {}", self.row, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct RowErrors(pub Vec<RowError>);
+
+impl fmt::Display for RowErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid row(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {error}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum CatalogueError {
+    /// the file could not be read: {0}
+    Malformed(String),
+    /// nothing was imported: {0}
+    InvalidRows(RowErrors),
+    /// database error: {0}
+    Database(#[from] diesel::result::Error),
+    /// the catalogue could not be written: {0}
+    Write(String),
+}
+
+/// Reads the movies of a catalogue file, checking every row before returning so that all the
+/// problems in a file are reported at once.
+pub fn parse(format: CatalogueFormat, reader: impl Read) -> Result<Vec<CatalogueMovie>, CatalogueError> {
+    let rows: Vec<Result<CatalogueMovie, String>> = match format {
+        CatalogueFormat::Json => {
+            let values: Vec<serde_json::Value> =
+                serde_json::from_reader(reader).map_err(|e| CatalogueError::Malformed(e.to_string()))?;
+            values
+                .into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+                .collect()
+        }
+        CatalogueFormat::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader)
+            .deserialize()
+            .map(|row| row.map_err(|e: csv::Error| e.to_string()))
+            .collect(),
+    };
+
+    let mut movies = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, parsed) in rows.into_iter().enumerate() {
+        let row = index + 1;
+        let movie = match parsed {
+            Ok(movie) => movie,
+            Err(message) => {
+                errors.push(RowError { row, external_id: None, message });
+                continue;
+            }
+        };
+        let problem = if seen.insert(movie.external_id.clone()) {
+            validate(&movie).err()
+        } else {
+            Some("external_id appears more than once in the file".to_string())
+        };
+        match problem {
+            None => movies.push(movie),
+            Some(message) => errors.push(RowError { row, external_id: Some(movie.external_id), message }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(movies)
+    } else {
+        Err(CatalogueError::InvalidRows(RowErrors(errors)))
+    }
+}
+
+fn validate(movie: &CatalogueMovie) -> Result<(), String> {
+    let required = [
+        ("external_id", &movie.external_id, 64),
+        ("title", &movie.title, 255),
+        ("director", &movie.director, 255),
+    ];
+    for (field, value, max_length) in required {
+        if value.trim().is_empty() {
+            return Err(format!("{field} is required"));
+        }
+        if value.chars().count() > max_length {
+            return Err(format!("{field} is longer than {max_length} characters"));
+        }
+    }
+    if !(FIRST_MOVIE_YEAR..=LAST_MOVIE_YEAR).contains(&movie.year) {
+        return Err(format!("year {} is not between {FIRST_MOVIE_YEAR} and {LAST_MOVIE_YEAR}", movie.year));
+    }
+    if movie.poster.as_ref().is_some_and(|poster| poster.chars().count() > 255) {
+        return Err("poster is longer than 255 characters".to_string());
+    }
+    Ok(())
+}
+
+/// What an import changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+/// Inserts the movies whose external id is new and updates the others, in one transaction.
+/// Movies missing from `catalogue` are left alone.
+#[tracing::instrument(skip_all, fields(movies = catalogue.len()))]
+pub fn import(
+    conn: &mut MysqlConnection,
+    catalogue: &[CatalogueMovie],
+    audit: &AuditContext,
+) -> Result<ImportSummary, CatalogueError> {
+    conn.transaction(|conn| {
+        let external_ids: Vec<&str> = catalogue.iter().map(|movie| movie.external_id.as_str()).collect();
+        let existing: HashMap<String, CatalogueMovie> = movies::table
+            .filter(movies::external_id.eq_any(&external_ids))
+            .select(CatalogueMovie::as_select())
+            .load(conn)?
+            .into_iter()
+            .map(|movie| (movie.external_id.clone(), movie))
+            .collect();
+
+        let mut summary = ImportSummary::default();
+        let mut new_movies = Vec::new();
+        for movie in catalogue {
+            let Some(current) = existing.get(&movie.external_id) else {
+                new_movies.push(movie);
+                continue;
+            };
+            let updated = current.updated_by(movie);
+            if &updated == current {
+                summary.unchanged += 1;
+            } else {
+                diesel::update(movies::table.filter(movies::external_id.eq(&movie.external_id)))
+                    .set(&updated)
+                    .execute(conn)?;
+                summary.updated += 1;
+            }
+        }
+        if !new_movies.is_empty() {
+            summary.inserted = diesel::insert_into(movies::table).values(new_movies).execute(conn)?;
+        }
+
+        audit::record(
+            conn,
+            audit,
+            AuditAction::MoviesImported,
+            None,
+            None,
+            Some(serde_json::to_value(summary).expect("summaries serialize")),
+        )?;
+        if summary.inserted + summary.updated > 0 {
+            events::emit(conn, &DomainEvent::CatalogueChanged)?;
+        }
+        Ok(summary)
+    })
+}
+
+/// The whole catalogue, in the order the movies were added.
+#[tracing::instrument(skip_all)]
+pub fn export(conn: &mut MysqlConnection) -> QueryResult<Vec<CatalogueMovie>> {
+    movies::table
+        .order(movies::id.asc())
+        .select(CatalogueMovie::as_select())
+        .load(conn)
+}
+
+/// Writes `catalogue` in a form [`parse`] reads back.
+pub fn write(format: CatalogueFormat, catalogue: &[CatalogueMovie], writer: impl Write) -> Result<(), CatalogueError> {
+    match format {
+        CatalogueFormat::Json => {
+            serde_json::to_writer_pretty(writer, catalogue).map_err(|e| CatalogueError::Write(e.to_string()))
+        }
+        CatalogueFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            if catalogue.is_empty() {
+                // The header is otherwise written along with the first movie.
+                writer
+                    .write_record(["external_id", "title", "year", "director", "poster"])
+                    .map_err(|e| CatalogueError::Write(e.to_string()))?;
+            }
+            for movie in catalogue {
+                writer.serialize(movie).map_err(|e| CatalogueError::Write(e.to_string()))?;
+            }
+            writer.flush().map_err(|e| CatalogueError::Write(e.to_string()))
+        }
+    }
+}
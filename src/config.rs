@@ -0,0 +1,314 @@
+use chrono::{Duration, NaiveDateTime};
+use dotenvy::dotenv;
+use std::env;
+use std::str::FromStr;
+
+/// Application settings read from the environment at startup.
+#[derive(Debug, Clone, Default)]
+pub struct AppConfig {
+    pub booking_window: BookingWindow,
+    pub change_policy: ChangePolicy,
+    pub outbox: OutboxPolling,
+    pub shutdown: ShutdownTimings,
+    pub log_format: LogFormat,
+    pub tracing: TraceExport,
+    pub migrations: MigrationSettings,
+    pub posters: PosterStorage,
+    pub cache: CacheSettings,
+}
+
+impl AppConfig {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let defaults = BookingWindow::default();
+        AppConfig {
+            booking_window: BookingWindow {
+                opens_days_before: env_or("BOOKING_OPENS_DAYS_BEFORE", defaults.opens_days_before),
+                closes_minutes_after_start: env_or(
+                    "BOOKING_CLOSES_MINUTES_AFTER_START",
+                    defaults.closes_minutes_after_start,
+                ),
+            },
+            change_policy: ChangePolicy {
+                default_cutoff_minutes: env_or(
+                    "RESERVATION_CHANGE_CUTOFF_MINUTES",
+                    ChangePolicy::default().default_cutoff_minutes,
+                ),
+            },
+            outbox: OutboxPolling {
+                poll_interval_ms: env_or(
+                    "OUTBOX_POLL_INTERVAL_MS",
+                    OutboxPolling::default().poll_interval_ms,
+                ),
+                retention_hours: env_or(
+                    "OUTBOX_RETENTION_HOURS",
+                    OutboxPolling::default().retention_hours,
+                ),
+            },
+            shutdown: ShutdownTimings {
+                drain_delay_ms: env_or(
+                    "SHUTDOWN_DRAIN_DELAY_MS",
+                    ShutdownTimings::default().drain_delay_ms,
+                ),
+                timeout_ms: env_or(
+                    "SHUTDOWN_TIMEOUT_MS",
+                    ShutdownTimings::default().timeout_ms,
+                ),
+            },
+            log_format: env_or("LOG_FORMAT", LogFormat::default()),
+            tracing: TraceExport {
+                otlp_endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                    .ok()
+                    .filter(|endpoint| !endpoint.is_empty()),
+            },
+            migrations: MigrationSettings {
+                auto_migrate: env_or("AUTO_MIGRATE", MigrationSettings::default().auto_migrate),
+                lock_timeout_secs: env_or(
+                    "MIGRATION_LOCK_TIMEOUT_SECS",
+                    MigrationSettings::default().lock_timeout_secs,
+                ),
+            },
+            posters: PosterStorage {
+                dir: env_or("POSTER_DIR", PosterStorage::default().dir),
+                max_upload_bytes: env_or(
+                    "POSTER_MAX_UPLOAD_BYTES",
+                    PosterStorage::default().max_upload_bytes,
+                ),
+            },
+            cache: CacheSettings {
+                catalogue_ttl_secs: env_or(
+                    "CACHE_CATALOGUE_TTL_SECS",
+                    CacheSettings::default().catalogue_ttl_secs,
+                ),
+                schedule_ttl_secs: env_or(
+                    "CACHE_SCHEDULE_TTL_SECS",
+                    CacheSettings::default().schedule_ttl_secs,
+                ),
+            },
+        }
+    }
+}
+
+/// Reads and parses `name`, falling back to `default` when it is not set.
+pub(crate) fn env_or<T>(name: &str, default: T) -> T
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|e| panic!("{name} must be a valid value: {e}")),
+        Err(_) => default,
+    }
+}
+
+/// When a screening can be booked: from `opens_days_before` days before it starts until
+/// `closes_minutes_after_start` minutes after.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookingWindow {
+    pub opens_days_before: i64,
+    pub closes_minutes_after_start: i64,
+}
+
+impl Default for BookingWindow {
+    fn default() -> Self {
+        BookingWindow {
+            opens_days_before: 365,
+            closes_minutes_after_start: 15,
+        }
+    }
+}
+
+impl BookingWindow {
+    pub fn opens_at(&self, start: NaiveDateTime) -> NaiveDateTime {
+        start - Duration::days(self.opens_days_before)
+    }
+
+    pub fn closes_at(&self, start: NaiveDateTime) -> NaiveDateTime {
+        start + Duration::minutes(self.closes_minutes_after_start)
+    }
+
+    pub fn is_open(&self, start: &NaiveDateTime, now: &NaiveDateTime) -> bool {
+        self.opens_at(*start) <= *now && *now <= self.closes_at(*start)
+    }
+
+    /// Earliest and latest screening start times that can be booked at `now`.
+    pub fn bookable_starts(&self, now: NaiveDateTime) -> (NaiveDateTime, NaiveDateTime) {
+        (
+            now - Duration::minutes(self.closes_minutes_after_start),
+            now + Duration::days(self.opens_days_before),
+        )
+    }
+}
+
+/// Reservations can be moved or cancelled until a cut-off before the screening starts. Schedules
+/// may override the cut-off; `default_cutoff_minutes` applies to the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChangePolicy {
+    pub default_cutoff_minutes: i64,
+}
+
+impl Default for ChangePolicy {
+    fn default() -> Self {
+        ChangePolicy {
+            default_cutoff_minutes: 30,
+        }
+    }
+}
+
+impl ChangePolicy {
+    /// Last moment a reservation for a screening starting at `start` can be changed.
+    pub fn deadline(&self, start: NaiveDateTime, schedule_cutoff_minutes: Option<i32>) -> NaiveDateTime {
+        let cutoff = schedule_cutoff_minutes.map_or(self.default_cutoff_minutes, i64::from);
+        start - Duration::minutes(cutoff)
+    }
+}
+
+/// How domain events travel between replicas, which only share the database: through the
+/// `outbox` table, which every replica polls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutboxPolling {
+    pub poll_interval_ms: u64,
+    /// How long events are kept before they are deleted.
+    pub retention_hours: i64,
+}
+
+impl Default for OutboxPolling {
+    fn default() -> Self {
+        OutboxPolling {
+            poll_interval_ms: 1000,
+            retention_hours: 24,
+        }
+    }
+}
+
+impl OutboxPolling {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+/// How a replica stops on SIGTERM/SIGINT: readiness fails at once, new connections are accepted for
+/// `drain_delay_ms` more so the load balancer can take the node out, and whatever is still running
+/// `timeout_ms` after the signal is dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShutdownTimings {
+    pub drain_delay_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for ShutdownTimings {
+    fn default() -> Self {
+        ShutdownTimings {
+            drain_delay_ms: 5000,
+            timeout_ms: 25000,
+        }
+    }
+}
+
+impl ShutdownTimings {
+    pub fn drain_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.drain_delay_ms)
+    }
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// How log lines are written to stdout: human-readable text, or one JSON object per line for log
+/// collectors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format `{other}`, expected `text` or `json`")),
+        }
+    }
+}
+
+/// Where spans are exported to. Export is disabled unless an OTLP/HTTP collector endpoint such as
+/// `http://otel-collector:4318` is configured.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TraceExport {
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Whether the server applies pending migrations itself when it starts, and how long it waits for
+/// another server that is already migrating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MigrationSettings {
+    pub auto_migrate: bool,
+    pub lock_timeout_secs: u64,
+}
+
+impl Default for MigrationSettings {
+    fn default() -> Self {
+        MigrationSettings {
+            auto_migrate: false,
+            lock_timeout_secs: 60,
+        }
+    }
+}
+
+impl MigrationSettings {
+    pub fn lock_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.lock_timeout_secs)
+    }
+}
+
+/// Where uploaded posters are kept, a directory every server must share, and how large an upload
+/// may be.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PosterStorage {
+    pub dir: String,
+    pub max_upload_bytes: usize,
+}
+
+impl Default for PosterStorage {
+    fn default() -> Self {
+        PosterStorage {
+            dir: "posters".to_string(),
+            max_upload_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// How long the data behind the public pages is cached. Staff edits invalidate it on every
+/// server, so the catalogue can be kept long; screenings also show seat counts, which only the
+/// TTL refreshes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheSettings {
+    pub catalogue_ttl_secs: u64,
+    pub schedule_ttl_secs: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            catalogue_ttl_secs: 300,
+            schedule_ttl_secs: 10,
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn catalogue_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.catalogue_ttl_secs)
+    }
+
+    pub fn schedule_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.schedule_ttl_secs)
+    }
+}
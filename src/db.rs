@@ -1,19 +1,32 @@
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
-use diesel::sql_types::Integer;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use diesel::sql_types::{BigInt, Integer, Timestamp};
 use diesel::MysqlConnection;
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+use diesel::mysql::Mysql;
+use chrono::{Local, NaiveDateTime};
 use dotenvy::dotenv;
+use std::collections::BTreeMap;
 use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use diesel::dsl::{count_star};
 use crate::models::{
-    Movie, NewReservation, Reservation, ReservationDetail,
-    Room, Schedule, ReservationChangeset,
+    Movie, NewReservation, NewUser, Reservation, ReservationDetail, ReservationStatus,
+    Room, Schedule, ScheduleDisplayInfo, User,
 };
-use crate::schema::{movies, reservation, rooms, schedule};
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::config::{env_or, BookingWindow, ChangePolicy};
+use crate::events::{self, DomainEvent};
+use crate::schema::{movies, reservation, rooms, schedule, users};
 
 pub type MysqlPool = Pool<ConnectionManager<MysqlConnection>>;
 pub type MysqlPooledConnection = PooledConnection<ConnectionManager<MysqlConnection>>;
 
+/// How long a replica that failed a checkout is skipped before it is tried again.
+const REPLICA_RETRY_AFTER: Duration = Duration::from_secs(30);
+
 pub fn establish_connection_pool() -> MysqlPool {
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -22,78 +35,674 @@ pub fn establish_connection_pool() -> MysqlPool {
         .max_size(20)
         .min_idle(Some(5))
         .test_on_check_out(true)
+        .connection_timeout(Duration::from_millis(env_or("DATABASE_POOL_TIMEOUT_MS", 5000)))
         .build(manager)
         .expect("Could not build connection pool")
 }
 
+/// Builds the primary pool from `DATABASE_URL` and one pool per comma-separated URL in
+/// `DATABASE_READ_URLS`. Replica pools are built lazily so that a replica being down does not
+/// stop the server from starting.
+pub fn establish_pools() -> DbPools {
+    let primary = establish_connection_pool();
+    let replicas = env::var("DATABASE_READ_URLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(|url| {
+            Pool::builder()
+                .max_size(20)
+                .min_idle(Some(5))
+                .test_on_check_out(true)
+                .connection_timeout(Duration::from_millis(env_or("DATABASE_REPLICA_TIMEOUT_MS", 1000)))
+                .build_unchecked(ConnectionManager::<MysqlConnection>::new(url))
+        })
+        .collect();
+
+    DbPools::new(primary, replicas)
+}
+
+struct Replica {
+    /// Label of the replica in metrics.
+    name: String,
+    pool: MysqlPool,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn is_healthy(&self) -> bool {
+        let unhealthy_until = self.unhealthy_until.lock().unwrap_or_else(|e| e.into_inner());
+        unhealthy_until.is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn mark_unhealthy(&self) {
+        let mut unhealthy_until = self.unhealthy_until.lock().unwrap_or_else(|e| e.into_inner());
+        *unhealthy_until = Some(Instant::now() + REPLICA_RETRY_AFTER);
+    }
+}
+
+/// The primary database, which takes all writes and reads that must see them (bookings), and
+/// read replicas for everything else.
+pub struct DbPools {
+    primary: Arc<MysqlPool>,
+    replicas: Vec<Replica>,
+    next_replica: AtomicUsize,
+}
+
+impl DbPools {
+    pub fn new(primary: MysqlPool, replicas: Vec<MysqlPool>) -> Self {
+        DbPools {
+            primary: Arc::new(primary),
+            replicas: replicas
+                .into_iter()
+                .enumerate()
+                .map(|(index, pool)| Replica {
+                    name: format!("replica{index}"),
+                    pool,
+                    unhealthy_until: Mutex::new(None),
+                })
+                .collect(),
+            next_replica: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn primary(&self) -> &Arc<MysqlPool> {
+        &self.primary
+    }
+
+    /// Checks out a connection for read-only work from the replicas in turn, skipping those that
+    /// recently failed, and falls back to the primary when none is available.
+    fn read_connection(&self) -> Result<MysqlPooledConnection, PoolError> {
+        let start = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.replicas.len() {
+            let replica = &self.replicas[(start + offset) % self.replicas.len()];
+            if !replica.is_healthy() {
+                continue;
+            }
+            match checkout(&replica.pool, &replica.name) {
+                Ok(conn) => return Ok(conn),
+                Err(e) => {
+                    tracing::warn!("Read replica unavailable, skipping it for {REPLICA_RETRY_AFTER:?}: {e}");
+                    replica.mark_unhealthy();
+                }
+            }
+        }
+
+        checkout(&self.primary, PRIMARY_POOL)
+    }
+
+    /// Publishes the current size and usage of every pool as gauges.
+    pub fn record_pool_metrics(&self) {
+        let pools = std::iter::once((PRIMARY_POOL, &*self.primary))
+            .chain(self.replicas.iter().map(|replica| (replica.name.as_str(), &replica.pool)));
+        for (name, pool) in pools {
+            let state = pool.state();
+            let name = name.to_string();
+            metrics::gauge!("db_pool_max_connections", "pool" => name.clone()).set(pool.max_size() as f64);
+            metrics::gauge!("db_pool_connections", "pool" => name.clone(), "state" => "idle")
+                .set(state.idle_connections as f64);
+            metrics::gauge!("db_pool_connections", "pool" => name, "state" => "in_use")
+                .set((state.connections - state.idle_connections) as f64);
+        }
+    }
+}
+
+const PRIMARY_POOL: &str = "primary";
+
+/// Checks out a connection from `pool`, recording how long it took and whether it timed out.
+fn checkout(pool: &MysqlPool, name: &str) -> Result<MysqlPooledConnection, PoolError> {
+    let _span = tracing::info_span!("db.checkout", pool = name).entered();
+    let started = Instant::now();
+    let conn = pool.get();
+    metrics::histogram!("db_pool_wait_seconds", "pool" => name.to_string()).record(started.elapsed().as_secs_f64());
+    if conn.is_err() {
+        metrics::counter!("db_pool_checkout_failures_total", "pool" => name.to_string()).increment(1);
+    }
+    conn
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum BlockingError {
+    /// Could not get a database connection in time: {0}
+    Checkout(#[from] PoolError),
+    /// Database task failed: {0}
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Runs `f` with a pooled connection on tokio's blocking thread pool, so that waiting for a
+/// connection and the synchronous Diesel calls in `f` don't stall the async worker threads. `f`
+/// runs inside the caller's tracing span.
+pub async fn run<F, T>(pool: &Arc<MysqlPool>, f: F) -> Result<T, BlockingError>
+where
+    F: FnOnce(&mut MysqlConnection) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        let mut conn = checkout(&pool, PRIMARY_POOL)?;
+        Ok(f(&mut conn))
+    })
+    .await?
+}
+
+/// Like [`run`], but for read-only work that may see slightly stale data, which runs on a read
+/// replica when one is available.
+pub async fn run_read<F, T>(pools: &Arc<DbPools>, f: F) -> Result<T, BlockingError>
+where
+    F: FnOnce(&mut MysqlConnection) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let pools = pools.clone();
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        let mut conn = pools.read_connection()?;
+        Ok(f(&mut conn))
+    })
+    .await?
+}
+
+/// Wraps every query Diesel runs in a `db.query` span and logs it at debug level, or as a warning
+/// when it fails. Bind values are left out since they include password hashes.
+pub fn trace_queries() {
+    fn statement(query: &dyn diesel::connection::DebugQuery) -> String {
+        let query = query.to_string();
+        query.split(" -- binds:").next().unwrap_or_default().to_string()
+    }
+
+    fn instrumentation() -> Option<Box<dyn Instrumentation>> {
+        // A connection runs one query at a time, so one open span per connection is enough.
+        let mut query_span: Option<tracing::Span> = None;
+        Some(Box::new(move |event: InstrumentationEvent<'_>| match event {
+            InstrumentationEvent::StartQuery { query, .. } => {
+                query_span = Some(tracing::info_span!("db.query", "db.statement" = statement(query)));
+            }
+            InstrumentationEvent::FinishQuery { query, error, .. } => {
+                let span = query_span.take().unwrap_or_else(tracing::Span::none);
+                let _entered = span.enter();
+                let sql = statement(query);
+                match error {
+                    Some(error) => tracing::warn!(sql, %error, "Query failed"),
+                    None => tracing::debug!(sql, "Query finished"),
+                }
+            }
+            _ => {}
+        }))
+    }
+
+    if let Err(e) = diesel::connection::set_default_instrumentation(instrumentation) {
+        tracing::error!("Failed to install query tracing: {e}");
+    }
+}
+
+#[tracing::instrument(skip(conn))]
+pub fn get_user_by_id(conn: &mut MysqlConnection, id: i32) -> QueryResult<Option<User>> {
+    users::table
+        .find(id)
+        .select(User::as_select())
+        .first(conn)
+        .optional()
+}
+
+#[tracing::instrument(skip(conn))]
+pub fn get_user_by_email(conn: &mut MysqlConnection, email: &str) -> QueryResult<Option<User>> {
+    users::table
+        .filter(users::email.eq(email))
+        .select(User::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Creates the account `email` and returns its id.
+#[tracing::instrument(skip(conn, password_hash))]
+pub fn create_user(
+    conn: &mut MysqlConnection,
+    email: &str,
+    password_hash: &str,
+    ip_address: Option<String>,
+) -> QueryResult<i32> {
+    conn.transaction(|conn| {
+        diesel::insert_into(users::table)
+            .values(&NewUser { email, password: password_hash })
+            .execute(conn)?;
+        let user_id = users::table
+            .filter(users::email.eq(email))
+            .select(users::id)
+            .first::<i32>(conn)?;
+
+        audit::record(
+            conn,
+            &AuditContext::new(Some(user_id), ip_address),
+            AuditAction::Register,
+            None,
+            None,
+            Some(serde_json::json!({ "email": email })),
+        )?;
+        events::emit(conn, &DomainEvent::UserRegistered {
+            user_id,
+            email: email.to_string(),
+        })?;
+        Ok(user_id)
+    })
+}
+
+#[tracing::instrument(skip(conn))]
+pub fn get_room_by_id(conn: &mut MysqlConnection, room_id: i32) -> QueryResult<Room> {
+    rooms::table.find(room_id).select(Room::as_select()).first(conn)
+}
+
+#[tracing::instrument(skip_all)]
+pub fn get_all_rooms(conn: &mut MysqlConnection) -> QueryResult<Vec<Room>> {
+    rooms::table.order(rooms::id.asc()).select(Room::as_select()).load(conn)
+}
+
+#[tracing::instrument(skip(conn))]
 pub fn get_movie_by_id(conn: &mut MysqlConnection, movie_id: i32) -> QueryResult<Movie> {
-    movies::table.find(movie_id).first(conn)
+    movies::table.find(movie_id).select(Movie::as_select()).first(conn)
 }
 
+#[tracing::instrument(skip_all)]
 pub fn get_all_movies(conn: &mut MysqlConnection) -> QueryResult<Vec<Movie>> {
-    movies::table.load::<Movie>(conn)
+    movies::table.select(Movie::as_select()).load(conn)
 }
 
-pub fn get_schedules_with_details(
+/// Points the poster of `movie_id` at `poster` and returns the one it replaces.
+#[tracing::instrument(skip(conn, audit))]
+pub fn set_movie_poster(
     conn: &mut MysqlConnection,
-) -> QueryResult<Vec<(Schedule, Movie, Room)>> {
-    schedule::table
+    movie_id: i32,
+    poster: &str,
+    audit: &AuditContext,
+) -> QueryResult<Option<String>> {
+    conn.transaction(|conn| {
+        let previous = movies::table
+            .find(movie_id)
+            .select(movies::poster)
+            .first::<Option<String>>(conn)?;
+        diesel::update(movies::table.find(movie_id))
+            .set(movies::poster.eq(poster))
+            .execute(conn)?;
+        audit::record(
+            conn,
+            audit,
+            AuditAction::PosterChanged,
+            Some(audit::movie_target(movie_id)),
+            Some(serde_json::json!({ "poster": previous })),
+            Some(serde_json::json!({ "poster": poster })),
+        )?;
+        events::emit(conn, &DomainEvent::CatalogueChanged)?;
+        Ok(previous)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MovieSort {
+    #[default]
+    TitleAsc,
+    TitleDesc,
+    YearAsc,
+    YearDesc,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MovieFilter {
+    /// Prefix of the title or the director, matched so that the lookup can use their indexes.
+    pub search: Option<String>,
+    pub year_from: Option<i32>,
+    pub year_to: Option<i32>,
+    /// Only keep movies with at least one screening in `[from, to)` that is not cancelled.
+    pub showing_between: Option<(NaiveDateTime, NaiveDateTime)>,
+    pub sort: MovieSort,
+}
+
+fn filtered_movies(filter: &MovieFilter) -> movies::BoxedQuery<'static, Mysql> {
+    let mut query = movies::table.into_boxed();
+
+    if let Some(search) = &filter.search {
+        let pattern = format!("{}%", escape_like(search));
+        query = query.filter(
+            movies::title.like(pattern.clone()).or(movies::director.like(pattern)),
+        );
+    }
+    if let Some(year_from) = filter.year_from {
+        query = query.filter(movies::year.ge(year_from));
+    }
+    if let Some(year_to) = filter.year_to {
+        query = query.filter(movies::year.le(year_to));
+    }
+    if let Some((from, to)) = filter.showing_between {
+        query = query.filter(
+            movies::id.eq_any(
+                schedule::table
+                    .filter(schedule::date.ge(from))
+                    .filter(schedule::date.lt(to))
+                    .filter(schedule::cancelled_at.is_null())
+                    .select(schedule::movie_id),
+            ),
+        );
+    }
+
+    query
+}
+
+/// Returns one page of movies matching `filter` together with the total number of matches.
+#[tracing::instrument(skip_all)]
+pub fn search_movies(
+    conn: &mut MysqlConnection,
+    filter: &MovieFilter,
+    limit: i64,
+    offset: i64,
+) -> QueryResult<(Vec<Movie>, i64)> {
+    let total = filtered_movies(filter).count().get_result::<i64>(conn)?;
+
+    let query = filtered_movies(filter);
+    let query = match filter.sort {
+        MovieSort::TitleAsc => query.order((movies::title.asc(), movies::id.asc())),
+        MovieSort::TitleDesc => query.order((movies::title.desc(), movies::id.desc())),
+        MovieSort::YearAsc => query.order((movies::year.asc(), movies::title.asc())),
+        MovieSort::YearDesc => query.order((movies::year.desc(), movies::title.asc())),
+    };
+    let movies = query.limit(limit).offset(offset).select(Movie::as_select()).load(conn)?;
+
+    Ok((movies, total))
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// Lets `get_screenings` group by the primary keys of the joined tables while selecting all of
+// their columns, which MySQL accepts because those columns depend on the grouped keys.
+diesel::allow_columns_to_appear_in_same_group_by_clause!(
+    schedule::id, schedule::movie_id, schedule::room_id, schedule::date, schedule::change_cutoff_minutes,
+    movies::id, movies::title, movies::year, movies::director, movies::poster,
+    rooms::id, rooms::capacity, rooms::label,
+);
+
+#[derive(Debug, Default, Clone)]
+pub struct ScreeningFilter {
+    pub movie_id: Option<i32>,
+    /// Inclusive lower bound of the screening date.
+    pub from: Option<NaiveDateTime>,
+    /// Exclusive upper bound of the screening date.
+    pub to: Option<NaiveDateTime>,
+    pub schedule_ids: Option<Vec<i32>>,
+}
+
+/// Loads screenings matching `filter` with their movie, room and the number of seats still
+/// available, ordered by date, in a single aggregated query. Cancelled screenings are left out.
+#[tracing::instrument(skip_all)]
+pub fn get_screenings(
+    conn: &mut MysqlConnection,
+    filter: &ScreeningFilter,
+) -> QueryResult<Vec<ScheduleDisplayInfo>> {
+    let mut query = schedule::table
         .inner_join(movies::table)
         .inner_join(rooms::table)
-        .select((Schedule::as_select(), Movie::as_select(), Room::as_select()))
-        .load::<(Schedule, Movie, Room)>(conn)
+        .left_join(
+            reservation::table.on(reservation::schedule_id
+                .eq(schedule::id)
+                .and(reservation::status.eq(ReservationStatus::Active))),
+        )
+        .group_by((schedule::id, movies::id, rooms::id))
+        .select((
+            Schedule::as_select(),
+            Movie::as_select(),
+            Room::as_select(),
+            diesel::dsl::count(reservation::id.nullable()),
+        ))
+        .filter(schedule::cancelled_at.is_null())
+        .order((schedule::date.asc(), schedule::id.asc()))
+        .into_boxed();
+
+    if let Some(movie_id) = filter.movie_id {
+        query = query.filter(schedule::movie_id.eq(movie_id));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(schedule::date.ge(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(schedule::date.lt(to));
+    }
+    if let Some(schedule_ids) = &filter.schedule_ids {
+        query = query.filter(schedule::id.eq_any(schedule_ids.clone()));
+    }
+
+    let rows = query.load::<(Schedule, Movie, Room, i64)>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(schedule, movie, room, reserved)| ScheduleDisplayInfo {
+            available_seats: room.capacity - reserved as i32,
+            schedule,
+            movie,
+            room,
+        })
+        .collect())
 }
 
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum ReservationError {
+    /// Room capacity exceeded for schedule ID {0}
+    CapacityExceeded(i32),
+    /// Booking for this screening opens at {0}
+    BookingNotOpen(NaiveDateTime),
+    /// Booking for this screening closed at {0}
+    BookingClosed(NaiveDateTime),
+    /// This reservation could only be changed or cancelled until {0}
+    ChangesClosed(NaiveDateTime),
+    /// This screening has been cancelled
+    ScreeningCancelled,
+    /// Database error: {0}
+    Database(#[from] diesel::result::Error),
+}
+
+/// Fails unless the screening `schedule_id` can be booked right now according to `window`.
+fn check_booking_window(
+    conn: &mut MysqlConnection,
+    schedule_id: i32,
+    window: &BookingWindow,
+) -> Result<(), ReservationError> {
+    let (start, cancelled_at) = schedule::table
+        .find(schedule_id)
+        .select((schedule::date, schedule::cancelled_at))
+        .first::<(NaiveDateTime, Option<NaiveDateTime>)>(conn)?;
+    let now = Local::now().naive_local();
+
+    if cancelled_at.is_some() {
+        Err(ReservationError::ScreeningCancelled)
+    } else if now < window.opens_at(start) {
+        Err(ReservationError::BookingNotOpen(window.opens_at(start)))
+    } else if now > window.closes_at(start) {
+        Err(ReservationError::BookingClosed(window.closes_at(start)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Loads the change deadline of each of `res_ids` that is still active.
+fn load_change_deadlines(
+    conn: &mut MysqlConnection,
+    res_ids: &[i32],
+    policy: &ChangePolicy,
+) -> QueryResult<Vec<(i32, NaiveDateTime)>> {
+    let rows = reservation::table
+        .inner_join(schedule::table)
+        .filter(reservation::id.eq_any(res_ids))
+        .filter(reservation::status.eq(ReservationStatus::Active))
+        .select((reservation::id, schedule::date, schedule::change_cutoff_minutes))
+        .load::<(i32, NaiveDateTime, Option<i32>)>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(res_id, start, cutoff)| (res_id, policy.deadline(start, cutoff)))
+        .collect())
+}
+
+/// Fails if reservation `res_id` is past its change deadline under `policy`.
+fn check_change_deadline(
+    conn: &mut MysqlConnection,
+    res_id: i32,
+    policy: &ChangePolicy,
+) -> Result<(), ReservationError> {
+    let now = Local::now().naive_local();
+    match load_change_deadlines(conn, &[res_id], policy)?.first() {
+        None => Err(diesel::result::Error::NotFound.into()),
+        Some((_, deadline)) if now > *deadline => Err(ReservationError::ChangesClosed(*deadline)),
+        Some(_) => Ok(()),
+    }
+}
+
+diesel::define_sql_function!(fn last_insert_id() -> Unsigned<BigInt>);
+
+/// Loads reservation `res_id` whatever its status.
+fn find_reservation(conn: &mut MysqlConnection, res_id: i32) -> QueryResult<Reservation> {
+    reservation::table
+        .find(res_id)
+        .select(Reservation::as_select())
+        .first(conn)
+}
+
+#[tracing::instrument(skip_all, fields(schedule_id = new_reservation.schedule_id))]
 pub fn create_reservation(
     conn: &mut MysqlConnection,
     new_reservation: NewReservation,
-) -> QueryResult<i32> {
-    conn.transaction(|conn| {
+    window: &BookingWindow,
+    audit: &AuditContext,
+) -> Result<i32, ReservationError> {
+    let result = conn.transaction(|conn| {
+        check_booking_window(conn, new_reservation.schedule_id, window)?;
+
         diesel::insert_into(reservation::table)
             .values(&new_reservation)
             .execute(conn)?;
+        let res_id = diesel::select(last_insert_id()).get_result::<u64>(conn)? as i32;
 
         if check_if_capacity_exceeded(conn, new_reservation.schedule_id)? {
-            return Err(diesel::result::Error::RollbackTransaction);
+            return Err(ReservationError::CapacityExceeded(new_reservation.schedule_id));
         }
 
-        Ok(0)
-    })
+        let created = find_reservation(conn, res_id)?;
+        audit::record(
+            conn,
+            audit,
+            AuditAction::ReservationCreated,
+            Some(audit::reservation_target(res_id)),
+            None,
+            Some(audit::reservation_snapshot(&created)),
+        )?;
+        events::emit(conn, &DomainEvent::ReservationCreated {
+            reservation_id: res_id,
+            user_id: created.user_id,
+            schedule_id: created.schedule_id,
+        })?;
+
+        Ok(res_id)
+    });
+
+    match &result {
+        Ok(_) => metrics::counter!("reservations_created_total").increment(1),
+        Err(ReservationError::CapacityExceeded(_)) => {
+            metrics::counter!("reservation_capacity_rollbacks_total", "operation" => "create").increment(1)
+        }
+        Err(_) => {}
+    }
+    result
 }
 
+/// Moves reservation `reservation_id` to `new_schedule_id` on behalf of `audit.user_id`.
+///
+/// The old reservation is kept with status `Moved` and a new active one pointing back to it is
+/// created, which is returned.
+#[tracing::instrument(skip(conn, window, policy, audit))]
 pub fn update_reservation(
     conn: &mut MysqlConnection,
     reservation_id: i32,
-    changeset: ReservationChangeset,
-) -> QueryResult<Reservation> {
-    use crate::schema::reservation::dsl::*;
+    new_schedule_id: i32,
+    window: &BookingWindow,
+    policy: &ChangePolicy,
+    audit: &AuditContext,
+) -> Result<Reservation, ReservationError> {
+    let result = conn.transaction(|conn| {
+        check_change_deadline(conn, reservation_id, policy)?;
+        let current = get_reservation_by_id(conn, reservation_id)?;
+        if current.schedule_id == new_schedule_id {
+            return Ok(current);
+        }
+        check_booking_window(conn, new_schedule_id, window)?;
 
-    conn.transaction(|conn| {
-        let rows_affected = diesel::update(reservation.find(reservation_id))
-            .set(&changeset)
+        diesel::update(reservation::table.find(reservation_id))
+            .set((
+                reservation::status.eq(ReservationStatus::Moved),
+                reservation::changed_by.eq(audit.user_id),
+            ))
+            .execute(conn)?;
+        diesel::insert_into(reservation::table)
+            .values(&NewReservation {
+                user_id: current.user_id,
+                schedule_id: new_schedule_id,
+                changed_by: audit.user_id,
+                moved_from_id: Some(reservation_id),
+            })
             .execute(conn)?;
 
-        if rows_affected == 0 {
-            Err(diesel::result::Error::NotFound)
-        } else {
-            if let Some(form_schedule_id) = changeset.schedule_id {
-                if check_if_capacity_exceeded(conn, form_schedule_id)? {
-                    return Err(diesel::result::Error::RollbackTransaction);
-                }
-            }
+        if check_if_capacity_exceeded(conn, new_schedule_id)? {
+            return Err(ReservationError::CapacityExceeded(new_schedule_id));
+        }
 
-            reservation.find(reservation_id).first(conn)
+        let moved = reservation::table
+            .filter(reservation::moved_from_id.eq(reservation_id))
+            .select(Reservation::as_select())
+            .first(conn)?;
+        audit::record(
+            conn,
+            audit,
+            AuditAction::ReservationMoved,
+            Some(audit::reservation_target(reservation_id)),
+            Some(audit::reservation_snapshot(&current)),
+            Some(audit::reservation_snapshot(&moved)),
+        )?;
+        events::emit(conn, &DomainEvent::ReservationMoved {
+            reservation_id: moved.id,
+            moved_from_id: reservation_id,
+            user_id: moved.user_id,
+            from_schedule_id: current.schedule_id,
+            to_schedule_id: moved.schedule_id,
+        })?;
+
+        Ok(moved)
+    });
+
+    match &result {
+        Ok(_) => metrics::counter!("reservations_moved_total").increment(1),
+        Err(ReservationError::CapacityExceeded(_)) => {
+            metrics::counter!("reservation_capacity_rollbacks_total", "operation" => "move").increment(1)
         }
-    })
+        Err(_) => {}
+    }
+    result
 }
 
+/// Loads reservation `res_id` if it is still active.
+#[tracing::instrument(skip_all)]
 pub fn get_reservation_by_id(
     conn: &mut MysqlConnection,
     res_id: i32,
 ) -> QueryResult<Reservation> {
-    reservation::table.find(res_id).first(conn)
+    reservation::table
+        .find(res_id)
+        .filter(reservation::status.eq(ReservationStatus::Active))
+        .select(Reservation::as_select())
+        .first(conn)
 }
 
+#[tracing::instrument(skip_all)]
 pub fn get_reservations_by_user_id(
     conn: &mut MysqlConnection,
     user_id_param: i32,
@@ -101,42 +710,78 @@ pub fn get_reservations_by_user_id(
     use crate::schema::reservation::dsl::*;
     reservation
         .filter(user_id.eq(user_id_param))
+        .filter(status.eq(ReservationStatus::Active))
+        .select(Reservation::as_select())
         .load::<Reservation>(conn)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReservationPeriod {
+    #[default]
+    Upcoming,
+    /// Everything that is no longer an upcoming active reservation: past screenings as well as
+    /// cancelled, moved and expired reservations.
+    History,
+}
+
+/// Loads up to `limit` of the user's reservations for `period`, ordered by screening date
+/// (soonest first for upcoming, most recent first for history).
+///
+/// Pagination is keyset based: pass the `(schedule_date, reservation_id)` of the last row of the
+/// previous page as `after` to continue from it.
+#[tracing::instrument(skip_all)]
 pub fn get_reservations_with_details(
-    conn: &mut PooledConnection<ConnectionManager<MysqlConnection>>,
+    conn: &mut MysqlConnection,
     user_id: i32,
+    period: ReservationPeriod,
+    after: Option<(NaiveDateTime, i32)>,
+    limit: i64,
 ) -> QueryResult<Vec<ReservationDetail>> {
-    diesel::sql_query(
+    let (period_clause, cursor_cmp, order) = match period {
+        ReservationPeriod::Upcoming => ("r.status = 'active' AND s.date >= ?", ">", "ASC"),
+        ReservationPeriod::History => ("NOT (r.status = 'active' AND s.date >= ?)", "<", "DESC"),
+    };
+
+    let mut sql = format!(
         "SELECT
             r.id as reservation_id,
             u.email as user_email,
             m.title as movie_title,
             ro.label as room_label,
-            s.date as schedule_date
+            s.date as schedule_date,
+            r.status as status,
+            r.updated_at as updated_at
         FROM reservation r
         INNER JOIN users u ON r.user_id = u.id
         INNER JOIN schedule s ON r.schedule_id = s.id
         INNER JOIN movies m ON s.movie_id = m.id
         INNER JOIN rooms ro ON s.room_id = ro.id
-        WHERE u.id = ?"
-    )
+        WHERE u.id = ? AND {period_clause}"
+    );
+    if after.is_some() {
+        sql.push_str(&format!(
+            " AND (s.date {cursor_cmp} ? OR (s.date = ? AND r.id {cursor_cmp} ?))"
+        ));
+    }
+    sql.push_str(&format!(" ORDER BY s.date {order}, r.id {order} LIMIT ?"));
+
+    let mut query = diesel::sql_query(sql)
+        .into_boxed()
         .bind::<Integer, _>(user_id)
-        .load::<ReservationDetail>(conn)
-}
+        .bind::<Timestamp, _>(Local::now().naive_local());
+    if let Some((date, reservation_id)) = after {
+        query = query
+            .bind::<Timestamp, _>(date)
+            .bind::<Timestamp, _>(date)
+            .bind::<Integer, _>(reservation_id);
+    }
 
-pub fn get_reservations_count_for_schedule(
-    conn: &mut MysqlConnection,
-    schedule_id_param: i32,
-) -> QueryResult<i64> {
-    use crate::schema::reservation::dsl::*;
-    reservation
-        .filter(schedule_id.eq(schedule_id_param))
-        .count()
-        .get_result(conn)
+    query
+        .bind::<BigInt, _>(limit)
+        .load::<ReservationDetail>(conn)
 }
 
+#[tracing::instrument(skip_all)]
 pub fn check_if_users_reservation(conn: &mut MysqlConnection, res_ids: Vec<i32>, user_id_value: i32) -> QueryResult<bool> {
     use crate::schema::reservation::dsl::*;
 
@@ -144,25 +789,118 @@ pub fn check_if_users_reservation(conn: &mut MysqlConnection, res_ids: Vec<i32>,
     let result = reservation
         .filter(id.eq_any(res_ids))
         .filter(user_id.eq(user_id_value))
+        .filter(status.eq(ReservationStatus::Active))
         .select(count_star())
         .first::<i64>(conn).unwrap_or(0);
 
     Ok(result == res_ids_len as i64)
 }
 
-pub fn delete_reservation(conn: &mut MysqlConnection, res_id: i32) -> QueryResult<usize> {
+/// Cancels reservation `res_id` on behalf of `audit.user_id`, keeping it as history.
+#[tracing::instrument(skip(conn, policy, audit))]
+pub fn delete_reservation(
+    conn: &mut MysqlConnection,
+    res_id: i32,
+    policy: &ChangePolicy,
+    audit: &AuditContext,
+) -> Result<usize, ReservationError> {
     use crate::schema::reservation::dsl::*;
-    diesel::delete(reservation.filter(id.eq(res_id))).execute(conn)
+
+    let result = conn.transaction(|conn| {
+        check_change_deadline(conn, res_id, policy)?;
+        let before = find_reservation(conn, res_id)?;
+
+        let rows_affected = diesel::update(reservation.filter(id.eq(res_id)))
+            .set((status.eq(ReservationStatus::Cancelled), changed_by.eq(audit.user_id)))
+            .execute(conn)?;
+
+        let after = find_reservation(conn, res_id)?;
+        audit::record(
+            conn,
+            audit,
+            AuditAction::ReservationCancelled,
+            Some(audit::reservation_target(res_id)),
+            Some(audit::reservation_snapshot(&before)),
+            Some(audit::reservation_snapshot(&after)),
+        )?;
+        events::emit(conn, &DomainEvent::ReservationCancelled {
+            reservation_id: res_id,
+            user_id: after.user_id,
+            schedule_id: after.schedule_id,
+            cancelled_by: audit.user_id,
+        })?;
+
+        Ok(rows_affected)
+    });
+
+    if result.is_ok() {
+        metrics::counter!("reservations_cancelled_total").increment(1);
+    }
+    result
 }
 
+#[derive(Debug, Default)]
+pub struct BulkDeleteOutcome {
+    pub deleted: Vec<i32>,
+    /// Reservations kept because they are past their change deadline, with that deadline.
+    pub refused: Vec<(i32, NaiveDateTime)>,
+}
+
+/// Cancels those of `res_ids` that can still be cancelled under `policy` and reports the rest.
+#[tracing::instrument(skip_all)]
 pub fn delete_multiple_reservations(
     conn: &mut MysqlConnection,
-    res_ids: Vec<i32>
-) -> QueryResult<usize> {
+    res_ids: Vec<i32>,
+    policy: &ChangePolicy,
+    audit: &AuditContext,
+) -> QueryResult<BulkDeleteOutcome> {
     use crate::schema::reservation::dsl::*;
-    diesel::delete(reservation.filter(id.eq_any(res_ids))).execute(conn)
+
+    let result = conn.transaction(|conn| {
+        let now = Local::now().naive_local();
+        let (deletable, refused): (Vec<_>, Vec<_>) = load_change_deadlines(conn, &res_ids, policy)?
+            .into_iter()
+            .partition(|(_, deadline)| now <= *deadline);
+        let deleted: Vec<i32> = deletable.into_iter().map(|(res_id, _)| res_id).collect();
+
+        let before = reservation
+            .filter(id.eq_any(&deleted))
+            .select(Reservation::as_select())
+            .load::<Reservation>(conn)?;
+        if !deleted.is_empty() {
+            diesel::update(reservation.filter(id.eq_any(&deleted)))
+                .set((status.eq(ReservationStatus::Cancelled), changed_by.eq(audit.user_id)))
+                .execute(conn)?;
+        }
+
+        let refused_ids: Vec<i32> = refused.iter().map(|(res_id, _)| *res_id).collect();
+        audit::record(
+            conn,
+            audit,
+            AuditAction::ReservationsBulkCancelled,
+            None,
+            Some(before.iter().map(audit::reservation_snapshot).collect()),
+            Some(serde_json::json!({ "cancelled": deleted, "refused": refused_ids })),
+        )?;
+        for cancelled in &before {
+            events::emit(conn, &DomainEvent::ReservationCancelled {
+                reservation_id: cancelled.id,
+                user_id: cancelled.user_id,
+                schedule_id: cancelled.schedule_id,
+                cancelled_by: audit.user_id,
+            })?;
+        }
+
+        Ok(BulkDeleteOutcome { deleted, refused })
+    });
+
+    if let Ok(outcome) = &result {
+        metrics::counter!("reservations_cancelled_total").increment(outcome.deleted.len() as u64);
+    }
+    result
 }
 
+#[tracing::instrument(skip(conn))]
 pub fn check_if_capacity_exceeded(conn: &mut MysqlConnection, schedule_id: i32) -> QueryResult<bool> {
     #[derive(QueryableByName)]
     struct ExceededResult {
@@ -175,7 +913,7 @@ pub fn check_if_capacity_exceeded(conn: &mut MysqlConnection, schedule_id: i32)
             SELECT 1
             FROM schedule s
             JOIN rooms r ON s.room_id = r.id
-            LEFT JOIN reservation res ON s.id = res.schedule_id
+            LEFT JOIN reservation res ON s.id = res.schedule_id AND res.status = 'active'
             WHERE s.id = ?
             GROUP BY s.id, r.capacity
             HAVING COUNT(res.id) > r.capacity
@@ -190,3 +928,67 @@ pub fn check_if_capacity_exceeded(conn: &mut MysqlConnection, schedule_id: i32)
 
     Ok(result)
 }
+
+/// A screening holding more active reservations than its room has seats.
+#[derive(Debug, Clone, PartialEq, QueryableByName, serde::Serialize)]
+pub struct OverbookedScreening {
+    #[diesel(sql_type = Integer)]
+    pub schedule_id: i32,
+    #[diesel(sql_type = BigInt)]
+    pub reserved: i64,
+    #[diesel(sql_type = Integer)]
+    pub capacity: i32,
+}
+
+/// Screenings with more active reservations than seats. Bookings that would overbook are rolled
+/// back, so this is always empty unless that check is broken.
+#[tracing::instrument(skip_all)]
+pub fn get_overbooked_screenings(conn: &mut MysqlConnection) -> QueryResult<Vec<OverbookedScreening>> {
+    diesel::sql_query(
+        "SELECT s.id AS schedule_id, COUNT(res.id) AS reserved, r.capacity AS capacity
+        FROM schedule s
+        JOIN rooms r ON s.room_id = r.id
+        JOIN reservation res ON s.id = res.schedule_id AND res.status = 'active'
+        GROUP BY s.id, r.capacity
+        HAVING COUNT(res.id) > r.capacity"
+    )
+    .load(conn)
+}
+
+/// Marks active reservations for screenings that started before `started_before` as expired, with
+/// one `ReservationsExpired` event per screening.
+#[tracing::instrument(skip_all)]
+pub fn expire_reservations(
+    conn: &mut MysqlConnection,
+    started_before: NaiveDateTime,
+) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let past_schedules = schedule::table
+            .filter(schedule::date.lt(started_before))
+            .select(schedule::id);
+        let expiring: Vec<(i32, i32)> = reservation::table
+            .filter(reservation::status.eq(ReservationStatus::Active))
+            .filter(reservation::schedule_id.eq_any(past_schedules))
+            .select((reservation::id, reservation::schedule_id))
+            .for_update()
+            .load(conn)?;
+        if expiring.is_empty() {
+            return Ok(0);
+        }
+
+        let ids: Vec<i32> = expiring.iter().map(|(id, _)| *id).collect();
+        let expired = diesel::update(reservation::table.filter(reservation::id.eq_any(&ids)))
+            .set(reservation::status.eq(ReservationStatus::Expired))
+            .execute(conn)?;
+
+        let mut by_schedule: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+        for (id, schedule_id) in expiring {
+            by_schedule.entry(schedule_id).or_default().push(id);
+        }
+        for (schedule_id, reservation_ids) in by_schedule {
+            events::emit(conn, &DomainEvent::ReservationsExpired { schedule_id, reservation_ids })?;
+        }
+        Ok(expired)
+    })
+}
+
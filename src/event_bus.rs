@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use diesel::MysqlConnection;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::db::MysqlPool;
+use crate::events::{self, StoredEvent};
+use crate::shutdown::Shutdown;
+
+const BATCH_SIZE: i64 = 500;
+
+/// Outbox ids are allocated when a transaction inserts, not when it commits, so a missing id
+/// below the highest one seen may still show up. It is looked for again until it is this old,
+/// after which its transaction is assumed to have rolled back.
+const GAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers domain events written to the outbox by any replica to subscribers on this one.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<StoredEvent>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(4096);
+        EventBus { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<StoredEvent>> {
+        self.sender.subscribe()
+    }
+
+    fn publish(&self, event: StoredEvent) {
+        // Nobody may be listening, which is fine.
+        let _ = self.sender.send(Arc::new(event));
+    }
+}
+
+#[derive(Default)]
+struct OutboxCursor {
+    /// Highest id delivered, or `None` before the first poll, which starts from the current end
+    /// of the outbox rather than replaying it.
+    last_id: Option<i64>,
+    /// Skipped ids below `last_id`, with when they were first found missing.
+    gaps: HashMap<i64, Instant>,
+}
+
+fn poll(conn: &mut MysqlConnection, cursor: &mut OutboxCursor) -> diesel::QueryResult<Vec<StoredEvent>> {
+    let Some(last_id) = cursor.last_id else {
+        cursor.last_id = Some(events::latest_event_id(conn)?);
+        return Ok(Vec::new());
+    };
+    let now = Instant::now();
+    let mut delivered = Vec::new();
+
+    if !cursor.gaps.is_empty() {
+        let gap_ids: Vec<i64> = cursor.gaps.keys().copied().collect();
+        for event in events::load_events_by_ids(conn, &gap_ids)? {
+            cursor.gaps.remove(&event.id);
+            delivered.push(event);
+        }
+        cursor.gaps.retain(|_, missing_since| now.duration_since(*missing_since) < GAP_TIMEOUT);
+    }
+
+    let mut high = last_id;
+    for event in events::load_events_after(conn, last_id, BATCH_SIZE)? {
+        cursor.gaps.extend((high + 1..event.id).map(|id| (id, now)));
+        high = event.id;
+        delivered.push(event);
+    }
+    cursor.last_id = Some(high);
+
+    Ok(delivered)
+}
+
+/// Polls the outbox every `interval` and publishes new events to `bus`, until shutdown.
+pub fn spawn_outbox_poller(pool: Arc<MysqlPool>, bus: EventBus, interval: Duration, shutdown: Shutdown) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut cursor = OutboxCursor::default();
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            let pool = pool.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let events = match pool.get() {
+                    Ok(mut conn) => poll(&mut conn, &mut cursor).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                (cursor, events)
+            })
+            .await;
+
+            match result {
+                Ok((polled_cursor, events)) => {
+                    cursor = polled_cursor;
+                    match events {
+                        Ok(events) => events.into_iter().for_each(|event| bus.publish(event)),
+                        Err(e) => tracing::error!("Failed to poll the outbox: {e}"),
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Outbox poll panicked: {e}");
+                    cursor = OutboxCursor::default();
+                }
+            }
+        }
+    })
+}
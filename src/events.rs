@@ -0,0 +1,152 @@
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel::MysqlConnection;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{NewOutboxEntry, OutboxEntry};
+use crate::schema::outbox;
+
+/// Something that happened in the domain which other parts of the system, on any replica, may
+/// want to react to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DomainEvent {
+    ReservationCreated {
+        reservation_id: i32,
+        user_id: i32,
+        schedule_id: i32,
+    },
+    ReservationCancelled {
+        reservation_id: i32,
+        user_id: i32,
+        schedule_id: i32,
+        cancelled_by: Option<i32>,
+    },
+    /// A reservation was replaced by `reservation_id` for another schedule.
+    ReservationMoved {
+        reservation_id: i32,
+        moved_from_id: i32,
+        user_id: i32,
+        from_schedule_id: i32,
+        to_schedule_id: i32,
+    },
+    /// Active reservations for a screening that can no longer be booked were marked as expired.
+    ReservationsExpired {
+        schedule_id: i32,
+        reservation_ids: Vec<i32>,
+    },
+    /// The schedule itself was edited or cancelled.
+    ScheduleChanged {
+        schedule_id: i32,
+    },
+    UserRegistered {
+        user_id: i32,
+        email: String,
+    },
+    /// Movies were added or edited by staff.
+    CatalogueChanged,
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::ReservationCreated { .. } => "reservation_created",
+            DomainEvent::ReservationCancelled { .. } => "reservation_cancelled",
+            DomainEvent::ReservationMoved { .. } => "reservation_moved",
+            DomainEvent::ReservationsExpired { .. } => "reservations_expired",
+            DomainEvent::ScheduleChanged { .. } => "schedule_changed",
+            DomainEvent::UserRegistered { .. } => "user_registered",
+            DomainEvent::CatalogueChanged => "catalogue_changed",
+        }
+    }
+
+    /// Schedules whose seat availability the event changes.
+    pub fn affected_schedules(&self) -> Vec<i32> {
+        match self {
+            DomainEvent::ReservationCreated { schedule_id, .. }
+            | DomainEvent::ReservationCancelled { schedule_id, .. }
+            | DomainEvent::ReservationsExpired { schedule_id, .. }
+            | DomainEvent::ScheduleChanged { schedule_id } => vec![*schedule_id],
+            DomainEvent::ReservationMoved { from_schedule_id, to_schedule_id, .. } => {
+                vec![*from_schedule_id, *to_schedule_id]
+            }
+            DomainEvent::UserRegistered { .. } | DomainEvent::CatalogueChanged => Vec::new(),
+        }
+    }
+}
+
+/// An event read back from the outbox.
+#[derive(Debug, Clone)]
+pub struct StoredEvent {
+    pub id: i64,
+    pub event: DomainEvent,
+}
+
+/// Writes `event` to the outbox. Call it in the transaction making the change, so that the event
+/// is published exactly when the change is committed.
+#[tracing::instrument(skip_all)]
+pub fn emit(conn: &mut MysqlConnection, event: &DomainEvent) -> QueryResult<()> {
+    let payload = serde_json::to_string(event)
+        .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+
+    diesel::insert_into(outbox::table)
+        .values(&NewOutboxEntry {
+            event_type: event.event_type(),
+            payload,
+        })
+        .execute(conn)
+        .map(|_| ())
+}
+
+fn decode(entries: Vec<OutboxEntry>) -> Vec<StoredEvent> {
+    entries
+        .into_iter()
+        .filter_map(|entry| match serde_json::from_str(&entry.payload) {
+            Ok(event) => Some(StoredEvent {
+                id: entry.id,
+                event,
+            }),
+            Err(e) => {
+                tracing::error!("Skipping undecodable outbox event {} ({}): {e}", entry.id, entry.event_type);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Loads up to `limit` events with an id above `after_id`, in id order.
+pub fn load_events_after(conn: &mut MysqlConnection, after_id: i64, limit: i64) -> QueryResult<Vec<StoredEvent>> {
+    let entries = outbox::table
+        .filter(outbox::id.gt(after_id))
+        .order(outbox::id.asc())
+        .limit(limit)
+        .select(OutboxEntry::as_select())
+        .load(conn)?;
+
+    Ok(decode(entries))
+}
+
+/// Loads those of the events `ids` that exist by now.
+pub fn load_events_by_ids(conn: &mut MysqlConnection, ids: &[i64]) -> QueryResult<Vec<StoredEvent>> {
+    let entries = outbox::table
+        .filter(outbox::id.eq_any(ids))
+        .order(outbox::id.asc())
+        .select(OutboxEntry::as_select())
+        .load(conn)?;
+
+    Ok(decode(entries))
+}
+
+pub fn latest_event_id(conn: &mut MysqlConnection) -> QueryResult<i64> {
+    outbox::table
+        .select(diesel::dsl::max(outbox::id))
+        .first::<Option<i64>>(conn)
+        .map(|id| id.unwrap_or(0))
+}
+
+/// Deletes events older than `hours`, which every replica has long since seen. The age is measured
+/// on the database clock, which also set `created_at`.
+pub fn delete_events_older_than(conn: &mut MysqlConnection, hours: i64) -> QueryResult<usize> {
+    diesel::sql_query("DELETE FROM outbox WHERE created_at < NOW() - INTERVAL ? HOUR")
+        .bind::<BigInt, _>(hours)
+        .execute(conn)
+}
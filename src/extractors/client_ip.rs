@@ -0,0 +1,36 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+
+/// Address of the client, as reported by the load balancer in `X-Real-IP` or as the last hop of
+/// `X-Forwarded-For`, falling back to the peer address of the connection.
+///
+/// Earlier `X-Forwarded-For` entries come from the client itself and are never trusted. Values
+/// that are not IP addresses are ignored.
+pub struct ClientIp(pub Option<String>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok());
+        let parse = |value: &str| value.trim().parse::<IpAddr>().ok();
+
+        let ip = header("x-real-ip")
+            .and_then(parse)
+            .or_else(|| header("x-forwarded-for").and_then(|value| value.rsplit(',').next()).and_then(parse))
+            .or_else(|| {
+                parts
+                    .extensions
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip())
+            });
+
+        Ok(ClientIp(ip.map(|ip| ip.to_string())))
+    }
+}
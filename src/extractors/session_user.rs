@@ -1,15 +1,19 @@
-use axum::extract::FromRequestParts;
+use std::sync::Arc;
+
+use axum::extract::{FromRef, FromRequestParts};
 use axum::http::request::Parts;
 use tower_sessions::Session;
 use crate::models::User; // Adjust path to your User struct
+use crate::repositories::UserRepository;
 use crate::{SESSION_USER_KEY, AppError};
-use axum::response::Response;
 
+/// The signed-in user, loaded afresh for each request from the id kept in the session.
 pub struct OptionalUser(pub Option<User>);
 
 impl<S> FromRequestParts<S> for OptionalUser
 where
     S: Send + Sync,
+    Arc<dyn UserRepository>: FromRef<S>,
 {
     type Rejection = AppError;
 
@@ -18,11 +22,15 @@ where
             .await
             .map_err(|_| AppError::UnauthorizedError)?;
 
-        let user = session.get::<User>(SESSION_USER_KEY)
+        let user_id = session.get::<i32>(SESSION_USER_KEY)
             .await
             .map_err(|_| AppError::UnauthorizedError)?;
+        let Some(user_id) = user_id else {
+            return Ok(OptionalUser(None));
+        };
 
-        Ok(OptionalUser(user))
+        let users = Arc::<dyn UserRepository>::from_ref(state);
+        Ok(OptionalUser(users.find(user_id).await??))
     }
 }
 
@@ -32,6 +40,7 @@ pub struct RequiredUser(pub User);
 impl<S> FromRequestParts<S> for RequiredUser
 where
     S: Send + Sync,
+    Arc<dyn UserRepository>: FromRef<S>,
 {
     type Rejection = AppError;
 
@@ -40,3 +49,24 @@ where
         user.ok_or(AppError::UnauthorizedError).map(RequiredUser)
     }
 }
+
+
+/// A signed-in user with the staff flag set.
+pub struct StaffUser(pub User);
+
+impl<S> FromRequestParts<S> for StaffUser
+where
+    S: Send + Sync,
+    Arc<dyn UserRepository>: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let RequiredUser(user) = RequiredUser::from_request_parts(parts, state).await?;
+        if user.is_staff {
+            Ok(StaffUser(user))
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+}
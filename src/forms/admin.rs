@@ -0,0 +1,52 @@
+use serde::Deserialize;
+
+use crate::audit::{AuditAction, AuditFilter};
+use crate::forms::{empty_string_as_none, encode_query_value, MAX_PAGE};
+
+pub const AUDIT_ENTRIES_PER_PAGE: i64 = 50;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Email of the user whose entries are shown.
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub user: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub action: Option<AuditAction>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub page: Option<i64>,
+}
+
+impl AuditQuery {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, MAX_PAGE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page() - 1) * AUDIT_ENTRIES_PER_PAGE
+    }
+
+    pub fn to_filter(&self) -> AuditFilter<'_> {
+        AuditFilter {
+            user_email: self.user.as_deref(),
+            action: self.action,
+        }
+    }
+
+    pub fn is_action(&self, action: &AuditAction) -> bool {
+        self.action.as_ref() == Some(action)
+    }
+
+    /// Builds the `/admin/audit` URL for `page` while keeping the current filters.
+    pub fn page_href(&self, page: i64) -> String {
+        let mut params = Vec::new();
+        if let Some(user) = &self.user {
+            params.push(format!("user={}", encode_query_value(user)));
+        }
+        if let Some(action) = self.action {
+            params.push(format!("action={}", action.as_str()));
+        }
+        params.push(format!("page={page}"));
+
+        format!("/admin/audit?{}", params.join("&"))
+    }
+}
@@ -1 +1,39 @@
+pub mod admin;
 pub mod auth;
+pub mod movies;
+pub mod programme;
+
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+/// Highest page a paginated list accepts. Far beyond any real list, it keeps the offsets computed
+/// from a page number given in the query string from overflowing.
+pub const MAX_PAGE: i64 = 100_000;
+
+/// Treats an empty query parameter (as sent by a blank form input) as missing.
+pub fn empty_string_as_none<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = Option::<String>::deserialize(de)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(s) => s.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Percent-encodes `value` for use in a query string.
+pub fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b' ' => "+".to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
@@ -0,0 +1,101 @@
+use chrono::{Duration, Local};
+use serde::Deserialize;
+
+use crate::db::{MovieFilter, MovieSort};
+use crate::forms::{empty_string_as_none, encode_query_value, MAX_PAGE};
+
+pub const MOVIES_PER_PAGE: i64 = 20;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MovieSortParam {
+    #[default]
+    Title,
+    TitleDesc,
+    Year,
+    YearDesc,
+}
+
+impl MovieSortParam {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovieSortParam::Title => "title",
+            MovieSortParam::TitleDesc => "title_desc",
+            MovieSortParam::Year => "year",
+            MovieSortParam::YearDesc => "year_desc",
+        }
+    }
+}
+
+impl From<MovieSortParam> for MovieSort {
+    fn from(param: MovieSortParam) -> Self {
+        match param {
+            MovieSortParam::Title => MovieSort::TitleAsc,
+            MovieSortParam::TitleDesc => MovieSort::TitleDesc,
+            MovieSortParam::Year => MovieSort::YearAsc,
+            MovieSortParam::YearDesc => MovieSort::YearDesc,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct MovieQuery {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub q: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub year_from: Option<i32>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub year_to: Option<i32>,
+    #[serde(default)]
+    pub this_week: bool,
+    #[serde(default)]
+    pub sort: MovieSortParam,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub page: Option<i64>,
+}
+
+impl MovieQuery {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).clamp(1, MAX_PAGE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page() - 1) * MOVIES_PER_PAGE
+    }
+
+    pub fn to_filter(&self) -> MovieFilter {
+        let showing_between = self.this_week.then(|| {
+            let now = Local::now().naive_local();
+            (now, now + Duration::days(7))
+        });
+
+        MovieFilter {
+            search: self.q.clone(),
+            year_from: self.year_from,
+            year_to: self.year_to,
+            showing_between,
+            sort: self.sort.into(),
+        }
+    }
+
+    /// Builds the `/movies` URL for `page` while keeping the current filters.
+    pub fn page_href(&self, page: i64) -> String {
+        let mut params = Vec::new();
+        if let Some(q) = &self.q {
+            params.push(format!("q={}", encode_query_value(q)));
+        }
+        if let Some(year_from) = self.year_from {
+            params.push(format!("year_from={year_from}"));
+        }
+        if let Some(year_to) = self.year_to {
+            params.push(format!("year_to={year_to}"));
+        }
+        if self.this_week {
+            params.push("this_week=true".to_string());
+        }
+        params.push(format!("sort={}", self.sort.as_str()));
+        params.push(format!("page={page}"));
+
+        format!("/movies?{}", params.join("&"))
+    }
+}
@@ -0,0 +1,16 @@
+use chrono::{Local, NaiveDate};
+use serde::Deserialize;
+
+use crate::forms::empty_string_as_none;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ProgrammeQuery {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub date: Option<NaiveDate>,
+}
+
+impl ProgrammeQuery {
+    pub fn date(&self) -> NaiveDate {
+        self.date.unwrap_or_else(|| Local::now().date_naive())
+    }
+}
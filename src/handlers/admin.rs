@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    response::{Html, Redirect},
+};
+use std::sync::Arc;
+use crate::templates_structs::RenderTraced;
+
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::config::AppConfig;
+use crate::db::{self, DbPools};
+use crate::extractors::client_ip::ClientIp;
+use crate::events::DomainEvent;
+use crate::extractors::session_user::StaffUser;
+use crate::forms::admin::{AuditQuery, AUDIT_ENTRIES_PER_PAGE};
+use crate::page_cache::PageCaches;
+use crate::posters::{self, PosterStore};
+use crate::repositories::MovieRepository;
+use crate::templates_structs::{AdminMoviesTemplate, AuditLogTemplate};
+use crate::AppError;
+
+#[tracing::instrument(skip_all)]
+pub async fn audit_log_handler(
+    StaffUser(_staff): StaffUser,
+    State(pools): State<Arc<DbPools>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Html<String>, AppError> {
+    let (query, (entries, total)) = db::run_read(&pools, move |conn| {
+        let page = audit::search_audit_log(conn, &query.to_filter(), AUDIT_ENTRIES_PER_PAGE, query.offset());
+        page.map(|page| (query, page))
+    })
+    .await??;
+    let total_pages = (total + AUDIT_ENTRIES_PER_PAGE - 1) / AUDIT_ENTRIES_PER_PAGE;
+
+    let template = AuditLogTemplate {
+        entries,
+        actions: AuditAction::ALL.to_vec(),
+        query,
+        total,
+        total_pages,
+    };
+    Ok(Html(template.render_traced()?))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn movies_handler(
+    StaffUser(_staff): StaffUser,
+    State(movies): State<Arc<dyn MovieRepository>>,
+    State(config): State<Arc<AppConfig>>,
+) -> Result<Html<String>, AppError> {
+    let mut movies = movies.all().await??;
+    movies.sort_by(|a, b| a.title.cmp(&b.title));
+
+    let template = AdminMoviesTemplate {
+        movies,
+        max_upload_bytes: config.posters.max_upload_bytes,
+    };
+    Ok(Html(template.render_traced()?))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn upload_poster_handler(
+    StaffUser(staff): StaffUser,
+    ClientIp(ip): ClientIp,
+    State(movies): State<Arc<dyn MovieRepository>>,
+    State(store): State<Arc<dyn PosterStore>>,
+    State(caches): State<Arc<PageCaches>>,
+    Path(movie_id): Path<i32>,
+    multipart: Multipart,
+) -> Result<Redirect, AppError> {
+    let upload = poster_upload(multipart).await?;
+    // Checked before storing anything, so that no files are left behind for a missing movie.
+    movies.find(movie_id).await?.map_err(|e| match e {
+        diesel::result::Error::NotFound => AppError::NotFound,
+        _ => AppError::Database(e),
+    })?;
+
+    let poster = posters::store(store.as_ref(), upload).await?;
+    let audit = AuditContext::new(Some(staff.id), ip);
+    movies.set_poster(movie_id, poster, audit).await??;
+    // Other servers follow through the outbox; this one shows the new poster right away.
+    caches.invalidate(&DomainEvent::CatalogueChanged);
+    Ok(Redirect::to("/admin/movies"))
+}
+
+/// The contents of the `poster` file field.
+async fn poster_upload(mut multipart: Multipart) -> Result<Vec<u8>, AppError> {
+    let bad_request = |e: axum::extract::multipart::MultipartError| AppError::BadRequest(e.body_text());
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        if field.name() == Some("poster") {
+            let bytes = field.bytes().await.map_err(bad_request)?;
+            if bytes.is_empty() {
+                break;
+            }
+            return Ok(bytes.to_vec());
+        }
+    }
+    Err(AppError::BadRequest("choose an image to upload".to_string()))
+}
@@ -5,19 +5,22 @@ use axum::{
 };
 use tower_sessions::Session;
 use bcrypt::{hash, verify, DEFAULT_COST};
-use diesel::{prelude::*};
 use askama::Template;
+use crate::templates_structs::RenderTraced;
 use htmxtools::response::HxRedirect;
 use axum::http::Uri;
 use std::sync::Arc;
 
 use crate::{
+    audit::AuditContext,
+    db::BlockingError,
+    extractors::client_ip::ClientIp,
     forms::auth::{LoginForm, RegisterForm},
-    models::{NewUser, User},
-    schema::users::dsl::*,
-    AppError, MysqlPool, SESSION_USER_KEY
+    repositories::UserRepository,
+    AppError, SESSION_USER_KEY
 };
 
+#[tracing::instrument(skip_all)]
 pub async fn show_register() -> Result<impl IntoResponse, AppError> {
     #[derive(Debug, Template)]
     #[template(path = "register.html")]
@@ -28,24 +31,21 @@ pub async fn show_register() -> Result<impl IntoResponse, AppError> {
     let template = Tmpl {
         register_error: None
     };
-    Ok(Html(template.render()?))
+    Ok(Html(template.render_traced()?))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn handle_register(
-    State(pool): State<Arc<MysqlPool>>,
+    State(users): State<Arc<dyn UserRepository>>,
+    ClientIp(ip): ClientIp,
     Form(form): Form<RegisterForm>,
 ) -> Result<Response, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-    
-    let hashed_password = hash(form.password, DEFAULT_COST).unwrap();
-    let new_user = NewUser {
-        email: &form.email,
-        password: &hashed_password,
-    };
-
-    let result = diesel::insert_into(users)
-        .values(&new_user)
-        .execute(&mut conn);
+    // Hashing is as slow as a query by design, so it runs on the blocking pool as well.
+    let password = form.password;
+    let hashed_password = tokio::task::spawn_blocking(move || hash(password, DEFAULT_COST).unwrap())
+        .await
+        .map_err(BlockingError::from)?;
+    let result = users.register(form.email, hashed_password, ip).await?;
 
     match result {
         Ok(_) => Ok(HxRedirect::from(Uri::from_static("/login")).into_response()),
@@ -59,11 +59,12 @@ pub async fn handle_register(
             let template = Tmpl {
                 register_error: Some(error)
             };
-            Ok(Html(template.render()?).into_response())
+            Ok(Html(template.render_traced()?).into_response())
         }
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn show_login() -> Result<impl IntoResponse, AppError> {
     #[derive(Debug, Template)]
     #[template(path = "login.html")]
@@ -74,30 +75,34 @@ pub async fn show_login() -> Result<impl IntoResponse, AppError> {
     let template = Tmpl {
         login_error: None
     };
-    Ok(Html(template.render()?))
+    Ok(Html(template.render_traced()?))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn handle_login(
-    State(pool): State<Arc<MysqlPool>>,
+    State(users): State<Arc<dyn UserRepository>>,
     session: Session,
+    ClientIp(ip): ClientIp,
     Form(form): Form<LoginForm>,
 ) -> Result<Response, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-    
-    let result = (|| {
-        let user = users
-            .filter(email.eq(&form.email))
-            .first::<User>(&mut conn).map_err(|_| AppError::UserLoginError)?;
-
-        if !verify(form.password, &user.password).unwrap_or(false) {
-            return Err(AppError::UserLoginError);
-        }
-
-        Ok(user)
-    })();
+    let found = users.find_by_email(form.email.clone()).await??;
+    // Failed attempts are recorded against the account they targeted, if it exists.
+    let audit_context = AuditContext::new(found.as_ref().map(|user| user.id), ip);
+    let password = form.password;
+    let result = tokio::task::spawn_blocking(move || {
+        found
+            .filter(|user| verify(&password, &user.password).unwrap_or(false))
+            .ok_or(AppError::UserLoginError)
+    })
+    .await
+    .map_err(BlockingError::from)?;
+    users.record_login(form.email, audit_context, result.is_ok()).await??;
 
+    if result.is_err() {
+        metrics::counter!("login_failures_total").increment(1);
+    }
     if let Ok(user) = result { 
-        session.insert(SESSION_USER_KEY, user).await.unwrap();
+        session.insert(SESSION_USER_KEY, user.id).await.unwrap();
         Ok(HxRedirect::from(Uri::from_static("/")).into_response())
     } else {
         #[derive(Debug, Template)]
@@ -109,10 +114,11 @@ pub async fn handle_login(
         let template = Tmpl {
             login_error: Some(AppError::UserLoginError)
         };
-        Ok(Html(template.render()?).into_response())
+        Ok(Html(template.render_traced()?).into_response())
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn logout(session: Session) -> impl IntoResponse {
     session.delete().await.unwrap();
     Redirect::to("/")
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use tower_sessions::{session::Id, SessionStore};
+
+use crate::db::{self, MysqlPool};
+use crate::migrations;
+use crate::sessions::SharedSessionStore;
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+struct ComponentStatus {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl ComponentStatus {
+    fn ok() -> Self {
+        ComponentStatus { status: Status::Ok, detail: None }
+    }
+
+    fn fail(detail: impl ToString) -> Self {
+        ComponentStatus { status: Status::Fail, detail: Some(detail.to_string()) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+impl IntoResponse for HealthReport {
+    fn into_response(self) -> Response {
+        let status = match self.status {
+            Status::Ok => StatusCode::OK,
+            Status::Fail => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz() -> impl IntoResponse {
+    HealthReport {
+        status: Status::Ok,
+        components: BTreeMap::new(),
+    }
+}
+
+/// Readiness: this replica can serve traffic, i.e. it is not shutting down, it can check out a
+/// database connection, the database has every migration this build expects, and the session store
+/// answers.
+pub async fn readyz(
+    State(pool): State<Arc<MysqlPool>>,
+    State(sessions): State<SharedSessionStore>,
+    State(shutdown): State<Shutdown>,
+) -> impl IntoResponse {
+    let mut components = BTreeMap::new();
+
+    // Fail first so that the load balancer stops sending traffic while requests drain.
+    if shutdown.is_triggered() {
+        components.insert("server", ComponentStatus::fail("shutting down"));
+        return HealthReport { status: Status::Fail, components };
+    }
+
+    let pending = db::run(&pool, |conn| migrations::pending_migrations(conn).map_err(|e| e.to_string())).await;
+    match pending {
+        Ok(Ok(pending)) => {
+            components.insert("database", ComponentStatus::ok());
+            if pending.is_empty() {
+                components.insert("migrations", ComponentStatus::ok());
+            } else {
+                components.insert("migrations", ComponentStatus::fail(format!("pending: {}", pending.join(", "))));
+            }
+        }
+        Ok(Err(e)) => {
+            components.insert("database", ComponentStatus::ok());
+            components.insert("migrations", ComponentStatus::fail(e));
+        }
+        Err(e) => {
+            components.insert("database", ComponentStatus::fail(&e));
+            components.insert("migrations", ComponentStatus::fail("database unavailable"));
+        }
+    }
+
+    match sessions.load(&Id::default()).await {
+        Ok(_) => components.insert("sessions", ComponentStatus::ok()),
+        Err(e) => components.insert("sessions", ComponentStatus::fail(e)),
+    };
+
+    let status = if components.values().all(|component| component.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Fail
+    };
+    HealthReport { status, components }
+}
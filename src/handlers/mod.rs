@@ -1,18 +1,23 @@
+pub mod admin;
 pub mod movies;
 pub mod reservations;
 pub mod auth;
+pub mod health;
+pub mod posters;
+pub mod programme;
 
 use axum::response::{Html, IntoResponse};
-use askama::Template;
+use crate::templates_structs::RenderTraced;
 use crate::extractors::session_user::OptionalUser;
 use crate::templates_structs::IndexTemplate;
 use crate::AppError;
 
+#[tracing::instrument(skip_all)]
 pub async fn index_handler(
     OptionalUser(user_option): OptionalUser,
 ) -> Result<impl IntoResponse, AppError> {
     let template = IndexTemplate {
         user_option
     };
-    Ok(Html(template.render()?))
+    Ok(Html(template.render_traced()?))
 }
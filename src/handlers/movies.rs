@@ -1,36 +1,87 @@
 use axum::{
-    extract::{Path, State},
-    response::Html,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
 };
 use std::sync::Arc;
-use askama::Template;
+use crate::templates_structs::RenderTraced;
+use chrono::Local;
+use htmxtools::request::HxTarget;
 
-use crate::db::MysqlPool;
-use crate::{db, AppError};
-use crate::templates_structs::{MoviesTemplate, MovieTemplate};
+use crate::config::AppConfig;
+use crate::db::ScreeningFilter;
+use crate::AppError;
+use crate::forms::movies::{MovieQuery, MOVIES_PER_PAGE};
+use crate::page_cache::{self, PageCaches};
+use crate::repositories::{MovieRepository, ScheduleRepository};
+use crate::templates_structs::{MoviesTemplate, MoviesResultsTemplate, MovieTemplate};
 
-pub async fn movies_handler(
-    State(pool): State<Arc<MysqlPool>>,
-) -> Result<Html<String>, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
+const MOVIES_RESULTS_TARGET: &str = "movies-results";
 
-    let movies = db::get_all_movies(&mut conn).map_err(AppError::Database)?;
+#[tracing::instrument(skip_all)]
+pub async fn movies_handler(
+    State(movies): State<Arc<dyn MovieRepository>>,
+    State(caches): State<Arc<PageCaches>>,
+    hx_target: Option<HxTarget>,
+    headers: HeaderMap,
+    Query(query): Query<MovieQuery>,
+) -> Result<Response, AppError> {
+    let loaded_query = query.clone();
+    let page = caches
+        .movies
+        .get_or_load(query.page_href(query.page()), || async move {
+            movies
+                .search(loaded_query.to_filter(), MOVIES_PER_PAGE, loaded_query.offset())
+                .await?
+                .map_err(AppError::Database)
+        })
+        .await?;
+    let (movies, total) = page.value.as_ref().clone();
+    let total_pages = (total + MOVIES_PER_PAGE - 1) / MOVIES_PER_PAGE;
 
-    let template = MoviesTemplate { movies };
-    Ok(Html(template.render()?))
+    let body = if hx_target.as_deref() == Some(MOVIES_RESULTS_TARGET) {
+        MoviesResultsTemplate { movies, query, total, total_pages }.render_traced()?
+    } else {
+        MoviesTemplate { movies, query, total, total_pages }.render_traced()?
+    };
+    Ok(page_cache::conditional_html(&headers, body, page.loaded_at))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn movie_handler(
-    State(pool): State<Arc<MysqlPool>>,
+    State(movies): State<Arc<dyn MovieRepository>>,
+    State(schedules): State<Arc<dyn ScheduleRepository>>,
+    State(caches): State<Arc<PageCaches>>,
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
     Path(movie_id): Path<i32>,
-) -> Result<Html<String>, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
+) -> Result<Response, AppError> {
+    let now = Local::now().naive_local();
+    let cached = caches
+        .movie
+        .get_or_load(movie_id, || async move {
+            let movie = movies.find(movie_id).await?.map_err(|e| match e {
+                diesel::result::Error::NotFound => AppError::NotFound,
+                _ => AppError::Database(e),
+            })?;
 
-    let movie = db::get_movie_by_id(&mut conn, movie_id).map_err(|e| match e {
-        diesel::result::Error::NotFound => AppError::NotFound,
-        _ => AppError::Database(e),
-    })?;
+            let filter = ScreeningFilter {
+                movie_id: Some(movie.id),
+                from: Some(now),
+                ..Default::default()
+            };
+            let screenings = schedules.screenings(filter).await??;
+            Ok::<_, AppError>((movie, screenings))
+        })
+        .await?;
+    let (movie, screenings) = cached.value.as_ref().clone();
 
-    let template = MovieTemplate { movie };
-    Ok(Html(template.render()?))
+    let template = MovieTemplate {
+        movie,
+        // Cached screenings may have started since.
+        screenings: screenings.into_iter().filter(|screening| screening.schedule.date >= now).collect(),
+        booking_window: config.booking_window,
+        now,
+    };
+    Ok(page_cache::conditional_html(&headers, template.render_traced()?, cached.loaded_at))
 }
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use crate::posters::{self, PosterSize, PosterStore, PLACEHOLDER_SVG};
+use crate::AppError;
+
+/// Stored posters never change, since a new upload gets a new id.
+const POSTER_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+const PLACEHOLDER_CACHE_CONTROL: &str = "public, max-age=86400";
+
+#[tracing::instrument(skip(store))]
+pub async fn poster_handler(
+    State(store): State<Arc<dyn PosterStore>>,
+    Path((id, file)): Path<(String, String)>,
+) -> Result<Response, AppError> {
+    let size: PosterSize = file.parse().map_err(|_| AppError::NotFound)?;
+    if !posters::is_valid_id(&id) {
+        return Err(AppError::NotFound);
+    }
+
+    let bytes = store
+        .get(&posters::key(&id, size))
+        .await
+        .map_err(posters::PosterError::Storage)?
+        .ok_or(AppError::NotFound)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/jpeg"),
+            (header::CACHE_CONTROL, POSTER_CACHE_CONTROL),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+pub async fn placeholder_handler() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, PLACEHOLDER_CACHE_CONTROL),
+        ],
+        PLACEHOLDER_SVG,
+    )
+}
@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::Response,
+};
+use std::sync::Arc;
+use crate::templates_structs::RenderTraced;
+use chrono::{Days, Local};
+
+use crate::config::AppConfig;
+use crate::db::ScreeningFilter;
+use crate::AppError;
+use crate::forms::programme::ProgrammeQuery;
+use crate::models::{Movie, ScheduleDisplayInfo};
+use crate::page_cache::{self, PageCaches};
+use crate::repositories::ScheduleRepository;
+use crate::templates_structs::ProgrammeTemplate;
+
+#[tracing::instrument(skip_all)]
+pub async fn programme_handler(
+    State(schedules): State<Arc<dyn ScheduleRepository>>,
+    State(caches): State<Arc<PageCaches>>,
+    State(config): State<Arc<AppConfig>>,
+    headers: HeaderMap,
+    Query(query): Query<ProgrammeQuery>,
+) -> Result<Response, AppError> {
+    let date = query.date();
+    let previous_date = date.checked_sub_days(Days::new(1)).ok_or(AppError::BadRequest("Date out of range.".into()))?;
+    let next_date = date.checked_add_days(Days::new(1)).ok_or(AppError::BadRequest("Date out of range.".into()))?;
+
+    let filter = ScreeningFilter {
+        from: Some(date.and_hms_opt(0, 0, 0).unwrap_or_default()),
+        to: Some(next_date.and_hms_opt(0, 0, 0).unwrap_or_default()),
+        ..Default::default()
+    };
+    let screenings = caches
+        .programme
+        .get_or_load(date, || async move {
+            schedules.screenings(filter).await?.map_err(AppError::Database)
+        })
+        .await?;
+
+    let template = ProgrammeTemplate {
+        date,
+        previous_date,
+        next_date,
+        movies: group_by_movie(screenings.value.as_ref().clone()),
+        booking_window: config.booking_window,
+        now: Local::now().naive_local(),
+    };
+    Ok(page_cache::conditional_html(&headers, template.render_traced()?, screenings.loaded_at))
+}
+
+/// Groups screenings by movie, keeping movies in the order of their first screening.
+fn group_by_movie(screenings: Vec<ScheduleDisplayInfo>) -> Vec<(Movie, Vec<ScheduleDisplayInfo>)> {
+    let mut movies: Vec<(Movie, Vec<ScheduleDisplayInfo>)> = Vec::new();
+    for screening in screenings {
+        match movies.iter_mut().find(|(movie, _)| movie.id == screening.movie.id) {
+            Some((_, movie_screenings)) => movie_screenings.push(screening),
+            None => movies.push((screening.movie.clone(), vec![screening])),
+        }
+    }
+    movies
+}
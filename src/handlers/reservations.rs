@@ -1,19 +1,24 @@
 use axum::{
-    extract::{Path, State, Form},
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
+    extract::{Path, Query, State, Form},
+    response::{Html, IntoResponse, Response, sse::{Event, KeepAlive, Sse}},
 };
+use std::convert::Infallible;
 use std::sync::Arc;
-use askama::Template;
+use crate::templates_structs::RenderTraced;
+use chrono::{DateTime, Local, NaiveDateTime};
+use htmxtools::request::HxTarget;
 use serde::Deserialize;
-use serde::de::{self, Deserializer, SeqAccess, Visitor};
-use std::fmt;
-use diesel::{serialize::IsNull::No, Connection};
+use tokio_stream::{wrappers::{BroadcastStream, WatchStream}, Stream, StreamExt};
 use diesel::result::{Error as DieselError, DatabaseErrorKind};
-use crate::{db::MysqlPool, extractors::session_user::RequiredUser};
-use crate::models::{NewReservation, ReservationDetail, ReservationChangeset, ScheduleDisplayInfo};
-use crate::{db, AppError};
-use crate::db::check_if_users_reservation;
+use crate::extractors::{client_ip::ClientIp, session_user::RequiredUser};
+use crate::audit::AuditContext;
+use crate::availability::AvailabilityFeed;
+use crate::shutdown::Shutdown;
+use crate::models::{NewReservation, Reservation, ReservationDetail, ScheduleDisplayInfo};
+use crate::AppError;
+use crate::config::{AppConfig, BookingWindow};
+use crate::db::{ReservationError, ReservationPeriod, ScreeningFilter};
+use crate::repositories::{ReservationRepository, ScheduleRepository};
 use crate::templates_structs::{ReservationsListTemplate, ReservationFormTemplate};
 
 #[derive(Deserialize)]
@@ -21,6 +26,12 @@ pub struct CreateReservationForm {
     pub schedule_id: i32,
 }
 
+/// Query of `/reservations/new`, which movie pages and the programme link to with a screening preselected.
+#[derive(Deserialize)]
+pub struct NewReservationQuery {
+    pub schedule_id: Option<i32>,
+}
+
 #[derive(Deserialize)]
 pub struct UpdateReservationForm {
     pub schedule_id: i32,
@@ -29,184 +40,329 @@ pub struct UpdateReservationForm {
 #[derive(Debug, Deserialize)]
 pub struct BulkDeleteFormData {
     pub reservation_ids: String,
+    #[serde(default)]
+    pub tab: ReservationTab,
+    pub cursor: Option<String>,
+}
+
+pub const RESERVATIONS_PER_PAGE: i64 = 20;
+
+const RESERVATION_FORM_TARGET: &str = "reservation-form-container";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationTab {
+    #[default]
+    Upcoming,
+    History,
+}
+
+impl ReservationTab {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservationTab::Upcoming => "upcoming",
+            ReservationTab::History => "history",
+        }
+    }
+}
+
+impl From<ReservationTab> for ReservationPeriod {
+    fn from(tab: ReservationTab) -> Self {
+        match tab {
+            ReservationTab::Upcoming => ReservationPeriod::Upcoming,
+            ReservationTab::History => ReservationPeriod::History,
+        }
+    }
+}
+
+/// Which page of the reservations list is shown: the tab and the keyset cursor
+/// (`<screening unix timestamp>_<reservation id>` of the last row of the previous page).
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ReservationListQuery {
+    #[serde(default)]
+    pub tab: ReservationTab,
+    pub cursor: Option<String>,
 }
 
+impl ReservationListQuery {
+    fn after(&self) -> Option<(NaiveDateTime, i32)> {
+        let (timestamp, id) = self.cursor.as_deref()?.split_once('_')?;
+        let date = DateTime::from_timestamp(timestamp.parse().ok()?, 0)?.naive_utc();
+        Some((date, id.parse().ok()?))
+    }
+}
 
-pub async fn list_reservations_handler(RequiredUser(user): RequiredUser, pool: State<Arc<MysqlPool>>) -> Result<Html<String>, AppError> {
-    list_reservations(RequiredUser(user), pool, None)
+fn encode_cursor(reservation: &ReservationDetail) -> String {
+    format!(
+        "{}_{}",
+        reservation.schedule_date.and_utc().timestamp(),
+        reservation.reservation_id
+    )
 }
 
-pub fn list_reservations(RequiredUser(user): RequiredUser, State(pool): State<Arc<MysqlPool>>, error_message: Option<String>) -> Result<Html<String>, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
+#[tracing::instrument(skip_all)]
+pub async fn list_reservations_handler(
+    RequiredUser(user): RequiredUser,
+    reservations: State<Arc<dyn ReservationRepository>>,
+    Query(view): Query<ReservationListQuery>,
+) -> Result<Html<String>, AppError> {
+    list_reservations(RequiredUser(user), reservations, view, None).await
+}
 
-    let reservations = db::get_reservations_with_details(&mut conn, user.id)
-        .map_err(AppError::Database)?;
+async fn load_reservations_page(
+    reservations: &dyn ReservationRepository,
+    user_id: i32,
+    view: &ReservationListQuery,
+) -> Result<(Vec<ReservationDetail>, Option<String>), AppError> {
+    let mut page = reservations
+        .list_for_user(user_id, view.tab.into(), view.after(), RESERVATIONS_PER_PAGE + 1)
+        .await??;
+
+    let next_cursor = if page.len() as i64 > RESERVATIONS_PER_PAGE {
+        page.truncate(RESERVATIONS_PER_PAGE as usize);
+        page.last().map(encode_cursor)
+    } else {
+        None
+    };
+
+    Ok((page, next_cursor))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_reservations(
+    RequiredUser(user): RequiredUser,
+    State(reservations): State<Arc<dyn ReservationRepository>>,
+    view: ReservationListQuery,
+    error_message: Option<String>,
+) -> Result<Html<String>, AppError> {
+    let (reservations, next_cursor) = load_reservations_page(reservations.as_ref(), user.id, &view).await?;
 
     let template = ReservationsListTemplate {
-        reservations, error_message,
+        reservations, error_message, view, next_cursor,
     };
-    Ok(Html(template.render()?))
+    Ok(Html(template.render_traced()?))
 }
 
-pub async fn show_create_reservation_form(RequiredUser(user): RequiredUser, State(pool): State<Arc<MysqlPool>>) -> Result<Html<String>, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
+/// Screenings within the booking window offered in the reservation forms. When editing, the
+/// seat held by `current` is counted as available on its own schedule.
+async fn load_bookable_schedules(
+    schedules: &dyn ScheduleRepository,
+    window: &BookingWindow,
+    current: Option<&Reservation>,
+) -> Result<Vec<ScheduleDisplayInfo>, AppError> {
+    let (from, to) = window.bookable_starts(Local::now().naive_local());
+    let filter = ScreeningFilter {
+        from: Some(from),
+        to: Some(to),
+        ..Default::default()
+    };
+    let mut screenings = schedules.live_screenings(filter).await??;
+
+    if let Some(current) = current {
+        for info in screenings.iter_mut().filter(|info| info.schedule.id == current.schedule_id) {
+            info.available_seats = (info.available_seats + 1).min(info.room.capacity);
+        }
+    }
 
-    let schedules_with_details = db::get_schedules_with_details(&mut conn).map_err(AppError::Database)?;
+    Ok(screenings)
+}
 
-    let mut schedules_display_info: Vec<ScheduleDisplayInfo> = Vec::new();
-    for (schedule, movie, room) in schedules_with_details {
-        let current_reservations_count = db::get_reservations_count_for_schedule(&mut conn, schedule.id)
-            .map_err(AppError::Database)?;
-        let available_seats = room.capacity - current_reservations_count as i32;
-        schedules_display_info.push(ScheduleDisplayInfo {
-            schedule: schedule.clone(), // Clone to avoid move error
-            movie: movie.clone(),       // Clone to avoid move error
-            room: room.clone(),         // Clone to avoid move error
-            available_seats,
+/// Streams seat availability changes as server-sent events named `schedule-<id>`, whose data is
+/// the new label of that schedule's option in the reservation form. The stream ends on shutdown,
+/// and the SSE extension reconnects to another replica.
+pub async fn availability_events(
+    State(feed): State<AvailabilityFeed>,
+    State(shutdown): State<Shutdown>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // A client too slow to keep up skips the updates it missed rather than being disconnected.
+    let updates = BroadcastStream::new(feed.subscribe()).filter_map(|update| update.ok().map(Some));
+    let closing = WatchStream::new(shutdown.subscribe())
+        .filter(|triggered| *triggered)
+        .map(|_| None);
+    let events = updates
+        .merge(closing)
+        .take_while(Option::is_some)
+        .filter_map(|update| {
+            update.map(|update| {
+                Ok(Event::default()
+                    .event(format!("schedule-{}", update.schedule_id))
+                    .data(update.label))
+            })
         });
-    }
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn show_create_reservation_form(
+    RequiredUser(user): RequiredUser,
+    State(schedules): State<Arc<dyn ScheduleRepository>>,
+    State(config): State<Arc<AppConfig>>,
+    hx_target: Option<HxTarget>,
+    Query(query): Query<NewReservationQuery>,
+) -> Result<Html<String>, AppError> {
+    let schedules_display_info = load_bookable_schedules(schedules.as_ref(), &config.booking_window, None).await?;
 
     let template = ReservationFormTemplate {
         reservation: None,
         schedules: schedules_display_info,
+        selected_schedule_id: query.schedule_id,
+        standalone: hx_target.as_deref() != Some(RESERVATION_FORM_TARGET),
     };
-    Ok(Html(template.render()?))
+    Ok(Html(template.render_traced()?))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn create_reservation(
     RequiredUser(user): RequiredUser,
-    State(pool): State<Arc<MysqlPool>>,
+    ClientIp(ip): ClientIp,
+    State(reservations): State<Arc<dyn ReservationRepository>>,
+    State(config): State<Arc<AppConfig>>,
     Form(form): Form<CreateReservationForm>,
 ) -> Result<Response, AppError> {
-
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-
     let new_reservation = NewReservation {
         user_id: user.id,
         schedule_id: form.schedule_id,
+        changed_by: Some(user.id),
+        moved_from_id: None,
     };
+    let window = config.booking_window;
+    let audit = AuditContext::new(Some(user.id), ip);
+    let result = reservations.create(new_reservation, window, audit).await?;
 
-    match db::create_reservation(&mut conn, new_reservation) {
+    match result {
         Ok(_) => {
-            Ok(list_reservations(RequiredUser(user), State(pool), None).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), None).await.into_response())
         }
-        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info)) => {
+        Err(ReservationError::Database(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info))) => {
             let user_friendly_error = Some("This user already has a reservation for the selected schedule.".to_string());
             tracing::warn!("Unique constraint violated: {:?}", info);
-            Ok(list_reservations(RequiredUser(user), State(pool), user_friendly_error).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), user_friendly_error).await.into_response())
         }
-        Err(DieselError::RollbackTransaction) => {
+        Err(ReservationError::CapacityExceeded(_)) => {
             let error_message = Some(format!(
                 "Room capacity exceeded for schedule ID {}",
                 form.schedule_id
             ));
 
-            Ok(list_reservations(RequiredUser(user), State(pool), error_message).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), error_message).await.into_response())
+        }
+        Err(e @ (ReservationError::BookingNotOpen(_) | ReservationError::BookingClosed(_) | ReservationError::ScreeningCancelled)) => {
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), Some(e.to_string())).await.into_response())
         }
         Err(e) => {
             tracing::error!("Failed to create reservation: {:?}", e);
             let error_message = Some(format!("Failed to create reservation: {}", e));
-            Ok(list_reservations(RequiredUser(user), State(pool), error_message).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), error_message).await.into_response())
         }
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn show_update_reservation_form(
     RequiredUser(user): RequiredUser,
     Path(id): Path<i32>,
-    State(pool): State<Arc<MysqlPool>>,
+    State(reservations): State<Arc<dyn ReservationRepository>>,
+    State(schedules): State<Arc<dyn ScheduleRepository>>,
+    State(config): State<Arc<AppConfig>>,
 ) -> Result<Html<String>, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-
-    let reservation = db::get_reservation_by_id(&mut conn, id).map_err(|e| match e {
-        diesel::result::Error::NotFound => AppError::NotFound,
+    let reservation = reservations.find_active(id).await?.map_err(|e| match e {
+        DieselError::NotFound => AppError::NotFound,
         _ => AppError::Database(e),
     })?;
-
-    let schedules_with_details = db::get_schedules_with_details(&mut conn).map_err(AppError::Database)?;
-
-    let mut schedules_display_info: Vec<ScheduleDisplayInfo> = Vec::new();
-    for (schedule, movie, room) in schedules_with_details {
-        let mut current_reservations_count = db::get_reservations_count_for_schedule(&mut conn, schedule.id)
-            .map_err(AppError::Database)?;
-
-        if reservation.schedule_id == schedule.id {
-            current_reservations_count = current_reservations_count.saturating_sub(1);
-        }
-
-        let available_seats = room.capacity - current_reservations_count as i32;
-        schedules_display_info.push(ScheduleDisplayInfo {
-            schedule: schedule.clone(),
-            movie: movie.clone(),
-            room: room.clone(),
-            available_seats,
-        });
-    }
+    let schedules_display_info =
+        load_bookable_schedules(schedules.as_ref(), &config.booking_window, Some(&reservation)).await?;
 
     let template = ReservationFormTemplate {
+        selected_schedule_id: Some(reservation.schedule_id),
         reservation: Some(reservation),
         schedules: schedules_display_info,
+        standalone: false,
     };
-    Ok(Html(template.render()?))
+    Ok(Html(template.render_traced()?))
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn update_reservation(
     RequiredUser(user): RequiredUser,
+    ClientIp(ip): ClientIp,
     Path(id): Path<i32>,
-    State(pool): State<Arc<MysqlPool>>,
+    State(reservations): State<Arc<dyn ReservationRepository>>,
+    State(config): State<Arc<AppConfig>>,
     Form(form): Form<UpdateReservationForm>,
 ) -> Result<Response, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-
-    let changeset = ReservationChangeset {
-        user_id: Some(user.id),
-        schedule_id: Some(form.schedule_id),
-    };
+    if !reservations.belong_to_user(vec![id], user.id).await?? {
+        return Err(AppError::UserLoginError);
+    }
+    let (window, policy) = (config.booking_window, config.change_policy);
+    let audit = AuditContext::new(Some(user.id), ip);
+    let result = reservations.move_to(id, form.schedule_id, window, policy, audit).await?;
 
-    match db::update_reservation(&mut conn, id, changeset) {
+    match result {
         Ok(_) => {
-            Ok(list_reservations(RequiredUser(user), State(pool), None).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), None).await.into_response())
         }
-        Err(DieselError::RollbackTransaction) => {
+        Err(ReservationError::Database(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info))) => {
+            let user_friendly_error = Some("This user already has a reservation for the selected schedule.".to_string());
+            tracing::warn!("Unique constraint violated: {:?}", info);
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), user_friendly_error).await.into_response())
+        }
+        Err(ReservationError::CapacityExceeded(_)) => {
             let error_message = Some(format!(
                 "Room capacity exceeded for new schedule ID {}",
                 form.schedule_id
             ));
-            Ok(list_reservations(RequiredUser(user), State(pool), error_message).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), error_message).await.into_response())
+        }
+        Err(e @ (ReservationError::BookingNotOpen(_) | ReservationError::BookingClosed(_) | ReservationError::ChangesClosed(_) | ReservationError::ScreeningCancelled)) => {
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), Some(e.to_string())).await.into_response())
         }
         Err(e) => {
             tracing::error!("Failed to update reservation {}: {:?}", id, e);
             let error_message = Some(format!("Failed to update reservation: {}", e));
-            Ok(list_reservations(RequiredUser(user), State(pool), error_message).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), error_message).await.into_response())
         }
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn delete_reservation(
     RequiredUser(user): RequiredUser,
+    ClientIp(ip): ClientIp,
     Path(id): Path<i32>,
-    State(pool): State<Arc<MysqlPool>>,
+    State(reservations): State<Arc<dyn ReservationRepository>>,
+    State(config): State<Arc<AppConfig>>,
 ) -> Result<Response, AppError> {
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-
-    if !check_if_users_reservation(&mut conn, vec![id], user.id)? {
+    if !reservations.belong_to_user(vec![id], user.id).await?? {
         return Err(AppError::UserLoginError);
     }
+    let audit = AuditContext::new(Some(user.id), ip);
+    let result = reservations.cancel(id, config.change_policy, audit).await?;
 
-    match db::delete_reservation(&mut conn, id) {
+    match result {
         Ok(_) => {
-            Ok(list_reservations(RequiredUser(user), State(pool), None).into_response())
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), None).await.into_response())
         }
-        Err(e) => {
+        Err(e @ ReservationError::ChangesClosed(_)) => {
+            Ok(list_reservations(RequiredUser(user), State(reservations), ReservationListQuery::default(), Some(e.to_string())).await.into_response())
+        }
+        Err(ReservationError::Database(e)) => {
             tracing::error!("Failed to delete reservation {}: {:?}", id, e);
             Err(AppError::Database(e))
         }
+        Err(e) => {
+            tracing::error!("Failed to delete reservation {}: {:?}", id, e);
+            Err(AppError::BadRequest(e.to_string()))
+        }
     }
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn delete_multiple_reservations(
     RequiredUser(user): RequiredUser,
-    State(pool): State<Arc<MysqlPool>>,
+    ClientIp(ip): ClientIp,
+    State(reservations): State<Arc<dyn ReservationRepository>>,
+    State(config): State<Arc<AppConfig>>,
     Form(form): Form<BulkDeleteFormData>,
 ) -> Result<Response, AppError> {
     let ids: Result<Vec<i32>, _> = form
@@ -220,18 +376,42 @@ pub async fn delete_multiple_reservations(
         Err(_) => return Err(AppError::BadRequest("Invalid reservation ID format.".into())),
     };
 
-    let mut conn = pool.get().map_err(|e| AppError::PoolError(e.to_string()))?;
-
     if reservation_ids.is_empty() {
         return Err(AppError::BadRequest("No reservations selected for deletion.".to_string()));
     }
 
-    if !check_if_users_reservation(&mut conn, reservation_ids.clone(), user.id)? {
+    let view = ReservationListQuery { tab: form.tab, cursor: form.cursor };
+    if !reservations.belong_to_user(reservation_ids.clone(), user.id).await?? {
         return Err(AppError::UserLoginError);
     }
 
-    match db::delete_multiple_reservations(&mut conn, reservation_ids) {
-        Ok(_) => Ok(list_reservations(RequiredUser(user), State(pool), None).into_response()),
+    // Only act on the rows of the page the form was submitted from.
+    let (visible, _) = load_reservations_page(reservations.as_ref(), user.id, &view).await?;
+    if !reservation_ids.iter().all(|id| visible.iter().any(|r| r.reservation_id == *id)) {
+        let error_message = Some("Some selected reservations are no longer listed. Please review the list and try again.".to_string());
+        return Ok(list_reservations(RequiredUser(user), State(reservations), view, error_message).await.into_response());
+    }
+
+    let audit = AuditContext::new(Some(user.id), ip);
+    let result = reservations.cancel_many(reservation_ids, config.change_policy, audit).await?;
+
+    match result {
+        Ok(outcome) => {
+            let error_message = (!outcome.refused.is_empty()).then(|| {
+                let refused = outcome
+                    .refused
+                    .iter()
+                    .map(|(id, deadline)| format!("#{id} (changes closed at {deadline})"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "Cancelled {} reservation(s). Could not cancel {}.",
+                    outcome.deleted.len(),
+                    refused
+                )
+            });
+            Ok(list_reservations(RequiredUser(user), State(reservations), view, error_message).await.into_response())
+        }
         Err(e) => {
             tracing::error!("Failed to delete multiple reservations: {:?}", e);
             Err(AppError::Database(e))
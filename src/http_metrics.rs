@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+
+use crate::db::DbPools;
+
+/// Installs the global Prometheus recorder. The returned handle renders everything recorded through
+/// the `metrics` macros, including the database and booking counters from the `db` module.
+pub fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new().install_recorder()
+}
+
+/// Counts requests and their latency by method, route pattern and status. Route patterns rather
+/// than raw paths keep the number of series bounded.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels).record(started.elapsed().as_secs_f64());
+    response
+}
+
+/// Prometheus scrape endpoint. Pool gauges are sampled here so they are current at scrape time.
+pub async fn metrics_handler(
+    State(handle): State<PrometheusHandle>,
+    State(pools): State<Arc<DbPools>>,
+) -> impl IntoResponse {
+    pools.record_pool_metrics();
+    handle.render()
+}
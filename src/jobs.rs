@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Local;
+use tokio::task::JoinHandle;
+
+use crate::config::AppConfig;
+use crate::db::{self, MysqlPool};
+use crate::events;
+use crate::sessions;
+use crate::shutdown::Shutdown;
+
+const EXPIRE_RESERVATIONS_EVERY: Duration = Duration::from_secs(5 * 60);
+
+/// Periodically marks reservations whose screening can no longer be booked as expired, until
+/// shutdown.
+pub fn spawn_reservation_expiry(pool: Arc<MysqlPool>, config: Arc<AppConfig>, shutdown: Shutdown) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRE_RESERVATIONS_EVERY);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            let started_before = Local::now().naive_local()
+                - chrono::Duration::minutes(config.booking_window.closes_minutes_after_start);
+            let result = db::run(&pool, move |conn| db::expire_reservations(conn, started_before)).await;
+
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(expired)) => tracing::info!("Expired {expired} reservation(s)"),
+                Ok(Err(e)) => tracing::error!("Failed to expire reservations: {e}"),
+                Err(e) => tracing::error!("Failed to run reservation expiry: {e}"),
+            }
+        }
+    })
+}
+
+const PURGE_OUTBOX_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically deletes outbox events older than the configured retention, until shutdown.
+pub fn spawn_outbox_cleanup(pool: Arc<MysqlPool>, config: Arc<AppConfig>, shutdown: Shutdown) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_OUTBOX_EVERY);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            let retention_hours = config.outbox.retention_hours;
+            let result = db::run(&pool, move |conn| events::delete_events_older_than(conn, retention_hours)).await;
+
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(deleted)) => tracing::info!("Deleted {deleted} old outbox event(s)"),
+                Ok(Err(e)) => tracing::error!("Failed to delete old outbox events: {e}"),
+                Err(e) => tracing::error!("Failed to run outbox cleanup: {e}"),
+            }
+        }
+    })
+}
+
+const PURGE_SESSIONS_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically deletes expired sessions, until shutdown.
+pub fn spawn_session_cleanup(pool: Arc<MysqlPool>, shutdown: Shutdown) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_SESSIONS_EVERY);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            let now = Local::now().naive_local();
+            let result = db::run(&pool, move |conn| sessions::delete_expired_sessions(conn, now)).await;
+
+            match result {
+                Ok(Ok(0)) => {}
+                Ok(Ok(deleted)) => tracing::info!("Deleted {deleted} expired session(s)"),
+                Ok(Err(e)) => tracing::error!("Failed to delete expired sessions: {e}"),
+                Err(e) => tracing::error!("Failed to run session cleanup: {e}"),
+            }
+        }
+    })
+}
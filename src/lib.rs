@@ -1,5 +1,98 @@
+use askama::Template;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
 use diesel::prelude::*;
 
+pub mod admin;
+pub mod audit;
+pub mod availability;
+pub mod catalogue;
+pub mod config;
 pub mod models;
 pub mod schema;
-pub mod db;
\ No newline at end of file
+pub mod db;
+pub mod events;
+pub mod event_bus;
+pub mod extractors;
+pub mod forms;
+pub mod handlers;
+pub mod http_metrics;
+pub mod jobs;
+pub mod migrations;
+pub mod page_cache;
+pub mod posters;
+pub mod repositories;
+pub mod request_id;
+pub mod routes;
+pub mod sessions;
+pub mod shutdown;
+pub mod state;
+pub mod telemetry;
+pub mod templates_structs;
+
+use templates_structs::ErrorTemplate;
+
+/// Session key of the signed-in user's id. Only the id is kept, so that sessions hold no password
+/// hashes and changes to the account apply straight away.
+pub const SESSION_USER_KEY: &str = "USER_ID";
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum AppError {
+    /// not found
+    NotFound,
+    /// could not render template
+    Render(#[from] askama::Error),
+    /// Database error: {0}
+    Database(#[from] diesel::result::Error),
+    /// {0}
+    Blocking(#[from] db::BlockingError),
+    /// Bad Request: {0}
+    BadRequest(String),
+    /// User login error
+    UserLoginError,
+    /// User register error
+    UserRegisterError,
+    /// UnauthorizedError
+    UnauthorizedError,
+    /// Forbidden
+    Forbidden,
+    /// {0}
+    Poster(#[from] posters::PosterError),
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Blocking(db::BlockingError::Checkout(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Blocking(db::BlockingError::Task(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::UserLoginError => StatusCode::IM_A_TEAPOT,
+            AppError::UserRegisterError => StatusCode::IM_A_TEAPOT,
+            AppError::UnauthorizedError => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::Poster(posters::PosterError::Storage(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Poster(_) => StatusCode::BAD_REQUEST,
+        };
+        if status.is_server_error() {
+            tracing::error!(error = ?self, "Request failed");
+        }
+        let tmpl = ErrorTemplate {
+            error_message: self.to_string(),
+            debug_info: format!("{:?}", self),
+            request_id: request_id::RequestId::current(),
+        };
+        let mut response = if let Ok(body) = tmpl.render() {
+            (status, Html(body)).into_response()
+        } else {
+            (status, format!("Error: {}", tmpl.error_message)).into_response()
+        };
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            // The pool was exhausted; another attempt shortly, possibly on another replica, may succeed.
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        }
+        response
+    }
+}
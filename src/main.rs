@@ -1,62 +1,90 @@
-use axum::{
-    extract::{Path, Query, State, Form},
-    http::StatusCode,
-    response::{Html, IntoResponse, Redirect, Response},
-    routing::{get, post, delete},
-    Router, serve,
-};
-use askama::Template;
-use chrono::NaiveDateTime;
-use diesel::result::Error::NotFound;
-use serde::Deserialize;
+use axum::serve;
+use std::env;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing::{Level, info};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-use diesel::prelude::*;
-use diesel::mysql::MysqlConnection;
-use diesel::r2d2::{ConnectionManager, Pool};
+use tracing::{info, warn};
 
 use tower_http::trace::TraceLayer;
-use dotenvy::dotenv;
-use std::env;
-use models::User;
-use tower_sessions::{Expiry, MemoryStore, Session, SessionManagerLayer};
+use tower_sessions::{Expiry, SessionManagerLayer};
 use tower_sessions::cookie::time::Duration;
 
-mod db;
-mod models;
-mod schema;
-mod routes;
-mod handlers;
-mod templates_structs;
-mod forms;
-mod extractors;
-
-use db::{establish_connection_pool, MysqlPool};
-use templates_structs::ErrorTemplate;
-
-const SESSION_USER_KEY: &str = "USER";
+use Cinema::availability::{self, AvailabilityFeed};
+use Cinema::config::{AppConfig, MigrationSettings};
+use Cinema::db::{self, establish_connection_pool, establish_pools, MysqlPool};
+use Cinema::event_bus::{self, EventBus};
+use Cinema::page_cache::{self, PageCaches};
+use Cinema::posters::FilesystemPosterStore;
+use Cinema::repositories::Repositories;
+use Cinema::sessions::{MysqlSessionStore, SharedSessionStore};
+use Cinema::shutdown::{self, Shutdown};
+use Cinema::state::AppState;
+use Cinema::{http_metrics, jobs, migrations, request_id, routes, telemetry, AppError};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
-        .init();
+    let config = AppConfig::from_env();
+    let telemetry = telemetry::init(&config).map_err(Error::Telemetry)?;
+    db::trace_queries();
+
+    match env::args().nth(1).as_deref() {
+        None | Some("serve") => {}
+        Some("migrate") => {
+            let result = migrate(&Arc::new(establish_connection_pool()), config.migrations).await;
+            let _ = tokio::task::spawn_blocking(move || telemetry.shutdown()).await;
+            return result;
+        }
+        Some(other) => return Err(Error::UnknownCommand(other.to_string())),
+    }
 
-    let pool = establish_connection_pool();
-    let shared_pool = Arc::new(pool);
+    let pools = Arc::new(establish_pools());
+    let session_store = SharedSessionStore::new(MysqlSessionStore::new(pools.primary().clone()));
+    let posters = Arc::new(FilesystemPosterStore::new(&config.posters.dir));
+    let caches = Arc::new(PageCaches::new(&config.cache));
+    let state = AppState {
+        repos: Repositories::mysql(pools.clone()),
+        pools,
+        config: Arc::new(config),
+        availability: AvailabilityFeed::new(),
+        sessions: session_store.clone(),
+        metrics: http_metrics::install_recorder().map_err(Error::Metrics)?,
+        posters,
+        caches,
+        shutdown: Shutdown::new(),
+    };
+    if state.config.migrations.auto_migrate {
+        migrate(state.pools.primary(), state.config.migrations).await?;
+    }
+    let shutdown = state.shutdown.clone();
+    tokio::spawn(shutdown::listen_for_signals(shutdown.clone()));
+
+    let primary = state.pools.primary().clone();
+    let event_bus = EventBus::new();
+    let background_tasks = [
+        jobs::spawn_reservation_expiry(primary.clone(), state.config.clone(), shutdown.clone()),
+        jobs::spawn_outbox_cleanup(primary.clone(), state.config.clone(), shutdown.clone()),
+        jobs::spawn_session_cleanup(primary.clone(), shutdown.clone()),
+        event_bus::spawn_outbox_poller(
+            primary.clone(),
+            event_bus.clone(),
+            state.config.outbox.poll_interval(),
+            shutdown.clone(),
+        ),
+        availability::spawn_availability_updater(primary, &event_bus, state.availability.clone(), shutdown.clone()),
+        page_cache::spawn_invalidator(state.caches.clone(), &event_bus, shutdown.clone()),
+    ];
+    let timings = state.config.shutdown;
 
-    let session_store = MemoryStore::default();
     let session_layer = SessionManagerLayer::new(session_store)
         .with_secure(false)
         .with_expiry(Expiry::OnInactivity(Duration::hours(3)));
 
-    let app = routes::app_router(shared_pool.clone())
+    let app = routes::app_router(state.clone())
+        .route_layer(axum::middleware::from_fn(http_metrics::track_requests))
         .fallback(|| async { AppError::NotFound })
-        .layer(TraceLayer::new_for_http())
-        .layer(session_layer);
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::RequestSpan))
+        .layer(axum::middleware::from_fn(request_id::propagate))
+        .layer(session_layer)
+        .merge(routes::health_router(state));
 
     let listener = TcpListener::bind("0.0.0.0:8080")
         .await
@@ -65,57 +93,82 @@ async fn main() -> Result<(), Error> {
     if let Ok(addr) = listener.local_addr() {
         info!("Listening on http://{addr}/");
     }
-    serve(listener, app).await.map_err(Error::Run)
+    let server = serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown.triggered().await;
+                // Readiness already fails; keep accepting until the load balancer has noticed.
+                tokio::time::sleep(timings.drain_delay()).await;
+                info!("No longer accepting connections, waiting for in-flight requests");
+            }
+        });
+
+    let deadline = async {
+        shutdown.triggered().await;
+        tokio::time::sleep(timings.timeout()).await;
+    };
+    tokio::pin!(deadline);
+
+    let drained = tokio::select! {
+        result = server => {
+            result.map_err(Error::Run)?;
+            true
+        }
+        _ = &mut deadline => {
+            warn!("Shutdown deadline passed, dropping in-flight requests");
+            false
+        }
+    };
+
+    if drained {
+        let background_finished = async {
+            for task in background_tasks {
+                let _ = task.await;
+            }
+        };
+        tokio::select! {
+            _ = background_finished => info!("Shut down cleanly"),
+            _ = &mut deadline => warn!("Shutdown deadline passed, dropping background tasks"),
+        }
+    }
+
+    // Flushing blocks on the exporter's HTTP client.
+    let _ = tokio::task::spawn_blocking(move || telemetry.shutdown()).await;
+    Ok(())
+}
+
+/// Applies pending migrations to the primary database. Servers starting together take turns, and
+/// all but the first find nothing left to apply.
+async fn migrate(pool: &Arc<MysqlPool>, settings: MigrationSettings) -> Result<(), Error> {
+    let lock_timeout = settings.lock_timeout();
+    let applied = db::run(pool, move |conn| migrations::run_pending_migrations(conn, lock_timeout))
+        .await
+        .map_err(Error::Database)?
+        .map_err(Error::Migrate)?;
+
+    if applied.is_empty() {
+        info!("Database schema is up to date");
+    } else {
+        info!("Applied migrations: {}", applied.join(", "));
+    }
+    Ok(())
 }
 
 #[derive(displaydoc::Display, thiserror::Error, Debug)]
 enum Error {
+    /// unknown command `{0}`, expected `serve` or `migrate`
+    UnknownCommand(String),
+    /// could not reach the database
+    Database(#[source] db::BlockingError),
+    /// could not migrate the database
+    Migrate(#[source] migrations::MigrationError),
     /// could not bind socket
     Bind(#[source] std::io::Error),
     /// could not run server
     Run(#[source] std::io::Error),
-}
-
-#[derive(Debug, displaydoc::Display, thiserror::Error)]
-pub enum AppError {
-    /// not found
-    NotFound,
-    /// could not render template
-    Render(#[from] askama::Error),
-    /// Database error: {0}
-    Database(#[from] diesel::result::Error),
-    /// Failed to get database connection from pool: {0}
-    PoolError(String),
-    /// Bad Request: {0}
-    BadRequest(String),
-    /// User login error
-    UserLoginError,
-    /// User register error
-    UserRegisterError,
-    /// UnauthorizedError
-    UnauthorizedError
-}
-
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let status = match &self {
-            AppError::NotFound => StatusCode::NOT_FOUND,
-            AppError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::PoolError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
-            AppError::UserLoginError => StatusCode::IM_A_TEAPOT,
-            AppError::UserRegisterError => StatusCode::IM_A_TEAPOT,
-            AppError::UnauthorizedError => StatusCode::UNAUTHORIZED
-        };
-        let tmpl = ErrorTemplate {
-            error_message: self.to_string(),
-            debug_info: format!("{:?}", self),
-        };
-        if let Ok(body) = tmpl.render() {
-            (status, Html(body)).into_response()
-        } else {
-            (status, format!("Error: {}", tmpl.error_message)).into_response()
-        }
-    }
+    /// could not install metrics recorder
+    Metrics(#[source] metrics_exporter_prometheus::BuildError),
+    /// could not set up span export
+    Telemetry(#[source] opentelemetry_otlp::ExporterBuildError),
 }
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::MysqlConnection;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+/// The migrations in `migrations/`, compiled into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Name of the MySQL user lock held while migrating, so that replicas starting together apply
+/// each migration once.
+const MIGRATION_LOCK: &str = "cinema_migrations";
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum MigrationError {
+    /// another server kept the migration lock for more than {0:?}
+    LockTimeout(Duration),
+    /// database error: {0}
+    Database(#[from] diesel::result::Error),
+    /// could not apply migrations: {0}
+    Migration(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Versions of the embedded migrations not applied to the database yet.
+pub fn pending_migrations(conn: &mut MysqlConnection) -> diesel::migration::Result<Vec<String>> {
+    Ok(conn
+        .pending_migrations(MIGRATIONS)?
+        .iter()
+        .map(|migration| migration.name().to_string())
+        .collect())
+}
+
+/// Applies the pending embedded migrations while holding the migration lock, waiting up to
+/// `lock_timeout` for another server to finish. Returns the versions applied, which are none if
+/// that server already applied them.
+pub fn run_pending_migrations(
+    conn: &mut MysqlConnection,
+    lock_timeout: Duration,
+) -> Result<Vec<String>, MigrationError> {
+    acquire_lock(conn, lock_timeout)?;
+    let applied = conn
+        .run_pending_migrations(MIGRATIONS)
+        .map(|versions| versions.iter().map(ToString::to_string).collect())
+        .map_err(MigrationError::Migration);
+    // The lock is also released when the connection closes, so a failed release only delays the
+    // next server until then.
+    if let Err(e) = release_lock(conn) {
+        tracing::warn!("Failed to release the migration lock: {e}");
+    }
+    applied
+}
+
+#[derive(QueryableByName)]
+struct LockResult {
+    /// 1 if the lock was obtained, 0 on timeout, NULL on error.
+    #[diesel(sql_type = Nullable<BigInt>)]
+    acquired: Option<i64>,
+}
+
+fn acquire_lock(conn: &mut MysqlConnection, timeout: Duration) -> Result<(), MigrationError> {
+    let result = diesel::sql_query("SELECT GET_LOCK(?, ?) AS acquired")
+        .bind::<Text, _>(MIGRATION_LOCK)
+        .bind::<BigInt, _>(timeout.as_secs() as i64)
+        .get_result::<LockResult>(conn)?;
+    match result.acquired {
+        Some(1) => Ok(()),
+        _ => Err(MigrationError::LockTimeout(timeout)),
+    }
+}
+
+fn release_lock(conn: &mut MysqlConnection) -> QueryResult<()> {
+    diesel::sql_query("SELECT RELEASE_LOCK(?) AS acquired")
+        .bind::<Text, _>(MIGRATION_LOCK)
+        .get_result::<LockResult>(conn)
+        .map(|_| ())
+}
@@ -2,7 +2,11 @@ use diesel::prelude::*;
 use chrono::NaiveDateTime;
 use crate::schema::*;
 use serde::{Deserialize, Serialize};
-use diesel::deserialize::QueryableByName;
+use diesel::deserialize::{self, FromSql, FromSqlRow, QueryableByName};
+use diesel::expression::AsExpression;
+use diesel::mysql::{Mysql, MysqlValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
 use serde;
 
 #[derive(Queryable, Identifiable, Selectable, Debug, PartialEq, serde::Deserialize, serde::Serialize, Clone)]
@@ -12,6 +16,9 @@ pub struct User {
     pub id: i32,
     pub email: String,
     pub password: String,
+    /// Staff can open the admin area, e.g. the audit log.
+    #[serde(default)]
+    pub is_staff: bool,
 }
 
 #[derive(Insertable)]
@@ -30,7 +37,7 @@ pub struct Movie {
     pub title: String,
     pub year: i32,
     pub director: String,
-    pub poster: String,
+    pub poster: Option<String>,
 }
 
 #[derive(Queryable, Identifiable, Selectable, Debug, PartialEq, Clone)]
@@ -50,9 +57,69 @@ pub struct Schedule {
     pub movie_id: i32,
     pub room_id: i32,
     pub date: NaiveDateTime,
+    /// Minutes before the start after which reservations can no longer be changed or cancelled.
+    /// `None` uses the configured default.
+    pub change_cutoff_minutes: Option<i32>,
 }
 
-#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq)]
+/// Lifecycle of a reservation. Only `Active` ones hold a seat; the others are kept as history.
+#[derive(AsExpression, FromSqlRow, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[diesel(sql_type = Varchar)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationStatus {
+    Active,
+    Cancelled,
+    /// Replaced by a reservation for another schedule, which points back via `moved_from_id`.
+    Moved,
+    /// The screening is over.
+    Expired,
+}
+
+impl ReservationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservationStatus::Active => "active",
+            ReservationStatus::Cancelled => "cancelled",
+            ReservationStatus::Moved => "moved",
+            ReservationStatus::Expired => "expired",
+        }
+    }
+}
+
+impl std::str::FromStr for ReservationStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(ReservationStatus::Active),
+            "cancelled" => Ok(ReservationStatus::Cancelled),
+            "moved" => Ok(ReservationStatus::Moved),
+            "expired" => Ok(ReservationStatus::Expired),
+            other => Err(format!("unknown reservation status: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ReservationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql<Varchar, Mysql> for ReservationStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Mysql>) -> serialize::Result {
+        <str as ToSql<Varchar, Mysql>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for ReservationStatus {
+    fn from_sql(bytes: MysqlValue<'_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Varchar, Mysql>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
+}
+
+#[derive(Queryable, Identifiable, Associations, Selectable, Debug, PartialEq, Clone)]
 #[diesel(belongs_to(User))]
 #[diesel(belongs_to(Schedule))]
 #[diesel(table_name = reservation)]
@@ -61,6 +128,12 @@ pub struct Reservation {
     pub id: i32,
     pub user_id: i32,
     pub schedule_id: i32,
+    pub status: ReservationStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// User who made the last change, e.g. the one who cancelled the reservation.
+    pub changed_by: Option<i32>,
+    pub moved_from_id: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -69,14 +142,8 @@ pub struct Reservation {
 pub struct NewReservation {
     pub user_id: i32,
     pub schedule_id: i32,
-}
-
-#[derive(AsChangeset, Deserialize, Serialize, Debug)]
-#[diesel(table_name = reservation)]
-#[diesel(check_for_backend(diesel::mysql::Mysql))]
-pub struct ReservationChangeset {
-    pub user_id: Option<i32>,
-    pub schedule_id: Option<i32>,
+    pub changed_by: Option<i32>,
+    pub moved_from_id: Option<i32>,
 }
 
 #[derive(Debug, QueryableByName)]
@@ -91,12 +158,70 @@ pub struct ReservationDetail {
     pub room_label: String,
     #[diesel(sql_type = diesel::sql_types::Timestamp)]
     pub schedule_date: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub status: ReservationStatus,
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub updated_at: NaiveDateTime,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ScheduleDisplayInfo {
     pub schedule: Schedule,
     pub movie: Movie,
     pub room: Room,
     pub available_seats: i32,
-}
\ No newline at end of file
+}
+#[derive(Queryable, Identifiable, Selectable, Debug, PartialEq, Clone)]
+#[diesel(table_name = audit_log)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct AuditEntry {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub user_id: Option<i32>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub before_data: Option<String>,
+    pub after_data: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct NewAuditEntry<'a> {
+    pub user_id: Option<i32>,
+    pub action: &'a str,
+    pub target: Option<String>,
+    pub ip_address: Option<&'a str>,
+    pub before_data: Option<String>,
+    pub after_data: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Debug, PartialEq, Clone)]
+#[diesel(table_name = outbox)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    pub event_type: String,
+    pub payload: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = outbox)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct NewOutboxEntry<'a> {
+    pub event_type: &'a str,
+    pub payload: String,
+}
+
+/// A session as kept in the `sessions` table; `data` is the whole session record as JSON.
+#[derive(Queryable, Selectable, Insertable, Debug, PartialEq, Clone)]
+#[diesel(table_name = sessions)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct StoredSession {
+    pub id: String,
+    pub data: String,
+    /// In local time, like the other timestamps.
+    pub expiry_date: NaiveDateTime,
+}
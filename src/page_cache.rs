@@ -0,0 +1,203 @@
+//! In-process cache of the data behind the catalogue and programme pages, which are read far more
+//! often than they change, and conditional responses so that browsers revalidate those pages
+//! instead of downloading them again.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use chrono::{DateTime, NaiveDate, Utc};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::config::CacheSettings;
+use crate::event_bus::EventBus;
+use crate::events::DomainEvent;
+use crate::models::{Movie, ScheduleDisplayInfo};
+use crate::shutdown::Shutdown;
+
+/// Entries kept per cache. Past it, expired entries are dropped, and if that is not enough the
+/// whole cache is, which only costs a reload.
+const MAX_ENTRIES: usize = 1000;
+
+/// A cached value and when it was loaded from the database.
+pub struct Cached<V> {
+    pub value: Arc<V>,
+    pub loaded_at: DateTime<Utc>,
+}
+
+impl<V> Clone for Cached<V> {
+    fn clone(&self) -> Self {
+        Cached { value: self.value.clone(), loaded_at: self.loaded_at }
+    }
+}
+
+struct Entry<V> {
+    cached: Cached<V>,
+    expires_at: Instant,
+}
+
+/// Values by key, each kept for `ttl` unless the cache is cleared first.
+pub struct TtlCache<K, V> {
+    name: &'static str,
+    ttl: Duration,
+    entries: Mutex<HashMap<K, Entry<V>>>,
+}
+
+impl<K: Eq + Hash, V> TtlCache<K, V> {
+    pub fn new(name: &'static str, ttl: Duration) -> Self {
+        TtlCache { name, ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    fn get(&self, key: &K) -> Option<Cached<V>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.cached.clone())
+    }
+
+    fn insert(&self, key: K, value: V) -> Cached<V> {
+        let cached = Cached { value: Arc::new(value), loaded_at: Utc::now() };
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires_at > now);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(key, Entry { cached: cached.clone(), expires_at: now + self.ttl });
+        cached
+    }
+
+    /// The cached value for `key`, or the one `load` returns, which is then cached. Concurrent
+    /// misses may each load the value; the last one is kept.
+    pub async fn get_or_load<F, Fut, E>(&self, key: K, load: F) -> Result<Cached<V>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        if let Some(cached) = self.get(&key) {
+            metrics::counter!("page_cache_requests_total", "cache" => self.name, "result" => "hit").increment(1);
+            return Ok(cached);
+        }
+        metrics::counter!("page_cache_requests_total", "cache" => self.name, "result" => "miss").increment(1);
+        let value = load().await?;
+        Ok(self.insert(key, value))
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        metrics::counter!("page_cache_invalidations_total", "cache" => self.name).increment(1);
+    }
+}
+
+/// A page of the movie catalogue and the number of movies matching its filters.
+pub type MoviePage = (Vec<Movie>, i64);
+
+/// The caches behind the public pages.
+pub struct PageCaches {
+    /// Pages of `/movies`, by their canonical URL.
+    pub movies: TtlCache<String, MoviePage>,
+    /// Movies of `/movies/{id}` with their upcoming screenings, by movie id.
+    pub movie: TtlCache<i32, (Movie, Vec<ScheduleDisplayInfo>)>,
+    /// Screenings of `/programme`, by day.
+    pub programme: TtlCache<NaiveDate, Vec<ScheduleDisplayInfo>>,
+}
+
+impl PageCaches {
+    pub fn new(settings: &CacheSettings) -> Self {
+        PageCaches {
+            movies: TtlCache::new("movies", settings.catalogue_ttl()),
+            movie: TtlCache::new("movie", settings.schedule_ttl()),
+            programme: TtlCache::new("programme", settings.schedule_ttl()),
+        }
+    }
+
+    /// Drops what `event` makes stale. Bookings are left to expire with the TTL: seat counts on
+    /// the cached pages may lag by that much, while the booking form follows them live.
+    pub fn invalidate(&self, event: &DomainEvent) {
+        match event {
+            // `/movies?this_week=true` lists movies by their screenings, so schedule changes make
+            // catalogue pages stale too.
+            DomainEvent::CatalogueChanged | DomainEvent::ScheduleChanged { .. } => self.clear(),
+            DomainEvent::ReservationCreated { .. }
+            | DomainEvent::ReservationCancelled { .. }
+            | DomainEvent::ReservationMoved { .. }
+            | DomainEvent::ReservationsExpired { .. }
+            | DomainEvent::UserRegistered { .. } => {}
+        }
+    }
+
+    pub fn clear(&self) {
+        self.movies.clear();
+        self.movie.clear();
+        self.programme.clear();
+    }
+}
+
+/// Invalidates `caches` as domain events from any replica arrive on `bus`, until shutdown.
+pub fn spawn_invalidator(caches: Arc<PageCaches>, bus: &EventBus, shutdown: Shutdown) -> JoinHandle<()> {
+    let mut events = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let received = tokio::select! {
+                received = events.recv() => received,
+                _ = shutdown.triggered() => break,
+            };
+            match received {
+                Ok(event) => caches.invalidate(&event.event),
+                // Any of the missed events may have made an entry stale.
+                Err(RecvError::Lagged(_)) => caches.clear(),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+/// `body` with an `ETag` and a `Last-Modified` of `loaded_at`, or an empty `304 Not Modified` if
+/// the request shows that the browser already has it.
+pub fn conditional_html(request_headers: &HeaderMap, body: String, loaded_at: DateTime<Utc>) -> Response {
+    let etag = etag(&body);
+    let last_modified = loaded_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let not_modified = match request_headers.get(header::IF_NONE_MATCH) {
+        // When both are sent, If-None-Match wins.
+        Some(if_none_match) => if_none_match.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag)
+        }),
+        None => request_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .is_some_and(|since| loaded_at.timestamp() <= since.timestamp()),
+    };
+
+    let headers = [
+        (header::ETAG, etag),
+        (header::LAST_MODIFIED, last_modified),
+        // Stored, but checked with the server before each use.
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+        // `/movies` answers htmx requests with just the results.
+        (header::VARY, "HX-Target".to_string()),
+    ];
+    if not_modified {
+        (StatusCode::NOT_MODIFIED, headers).into_response()
+    } else {
+        (headers, Html(body)).into_response()
+    }
+}
+
+/// Strong validator of `body`. The hasher is the same in every replica running the same build.
+fn etag(body: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
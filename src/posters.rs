@@ -0,0 +1,179 @@
+//! Movie posters uploaded by staff, stored by the server in a couple of sizes rather than
+//! hot-linked from third parties.
+
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use image::imageops::FilterType;
+use uuid::Uuid;
+
+use crate::models::Movie;
+
+/// Path under which stored posters are served. `movies.poster` holds `/posters/<id>` for them,
+/// and a full URL for posters still hot-linked from elsewhere.
+pub const POSTER_PATH_PREFIX: &str = "/posters/";
+
+/// Shown for movies without a poster, and by browsers when a hot-linked one fails to load.
+pub const PLACEHOLDER_PATH: &str = "/posters/placeholder.svg";
+
+pub const PLACEHOLDER_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="200" height="300" viewBox="0 0 200 300"><rect width="200" height="300" fill="#ddd"/><text x="100" y="155" font-family="sans-serif" font-size="16" fill="#888" text-anchor="middle">No poster</text></svg>"##;
+
+/// Quality of the JPEG thumbnails, out of 100.
+const JPEG_QUALITY: u8 = 85;
+
+/// The sizes a poster is stored in, all cropped to the 2:3 aspect ratio of the page layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PosterSize {
+    /// For the movie grid, shown at 200x300.
+    Small,
+    /// For the movie page, twice the displayed size for high-density screens.
+    Large,
+}
+
+impl PosterSize {
+    pub const ALL: [PosterSize; 2] = [PosterSize::Small, PosterSize::Large];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PosterSize::Small => "small",
+            PosterSize::Large => "large",
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            PosterSize::Small => (200, 300),
+            PosterSize::Large => (400, 600),
+        }
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.jpg", self.as_str())
+    }
+}
+
+impl FromStr for PosterSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PosterSize::ALL
+            .into_iter()
+            .find(|size| size.file_name() == s)
+            .ok_or_else(|| format!("unknown poster file: {s}"))
+    }
+}
+
+impl Movie {
+    /// Where the browser finds the poster of this movie in `size`.
+    pub fn poster_src(&self, size: PosterSize) -> String {
+        match self.poster.as_deref() {
+            Some(poster) => match poster.strip_prefix(POSTER_PATH_PREFIX) {
+                Some(id) => format!("{POSTER_PATH_PREFIX}{}", key(id, size)),
+                None => poster.to_string(),
+            },
+            None => PLACEHOLDER_PATH.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum PosterError {
+    /// The file is not a JPEG, PNG or WebP image: {0}
+    InvalidImage(#[from] image::ImageError),
+    /// The image is {0}x{1} pixels; posters need to be at least 400x600
+    TooSmall(u32, u32),
+    /// Could not store the poster: {0}
+    Storage(#[from] io::Error),
+}
+
+/// Where poster files live. Keys look like relative paths, `<id>/<size>.jpg`, and are never
+/// reused, so stored files can be cached forever.
+#[async_trait]
+pub trait PosterStore: Send + Sync {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()>;
+
+    /// The file stored under `key`, or `None` if there is none.
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// Keeps posters in a directory, which must be shared by all servers.
+#[derive(Debug, Clone)]
+pub struct FilesystemPosterStore {
+    root: PathBuf,
+}
+
+impl FilesystemPosterStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FilesystemPosterStore { root: root.into() }
+    }
+}
+
+#[async_trait]
+impl PosterStore for FilesystemPosterStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> io::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Written aside and renamed so that no reader ever sees half a file.
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.root.join(key)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The storage key of poster `id` in `size`.
+pub fn key(id: &str, size: PosterSize) -> String {
+    format!("{id}/{}", size.file_name())
+}
+
+/// Whether `id` can be a poster id, so that request paths never reach outside the store.
+pub fn is_valid_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Stores every size of the uploaded image and returns the value for `movies.poster`.
+#[tracing::instrument(skip_all, fields(bytes = upload.len()))]
+pub async fn store(store: &dyn PosterStore, upload: Vec<u8>) -> Result<String, PosterError> {
+    let resized = tokio::task::spawn_blocking(move || resize(&upload))
+        .await
+        .map_err(io::Error::other)??;
+
+    let id = Uuid::new_v4().simple().to_string();
+    for (size, bytes) in resized {
+        store.put(&key(&id, size), bytes).await?;
+    }
+    Ok(format!("{POSTER_PATH_PREFIX}{id}"))
+}
+
+fn resize(upload: &[u8]) -> Result<Vec<(PosterSize, Vec<u8>)>, PosterError> {
+    let image = image::load_from_memory(upload)?;
+    let (min_width, min_height) = PosterSize::Large.dimensions();
+    if image.width() < min_width || image.height() < min_height {
+        return Err(PosterError::TooSmall(image.width(), image.height()));
+    }
+
+    PosterSize::ALL
+        .into_iter()
+        .map(|size| {
+            let (width, height) = size.dimensions();
+            // JPEG has no alpha channel.
+            let resized = image.resize_to_fill(width, height, FilterType::Lanczos3).into_rgb8();
+            let mut bytes = Vec::new();
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY);
+            resized.write_with_encoder(encoder)?;
+            Ok((size, bytes))
+        })
+        .collect()
+}
+
@@ -0,0 +1,569 @@
+//! Repositories keeping everything in memory, for tests that should not need a MySQL cluster.
+//!
+//! They hold the invariants the schema and the queries in [`db`](crate::db) hold: emails are
+//! unique, a user has at most one active reservation per screening, screenings never have more
+//! active reservations than their room has seats, and bookings respect the booking window and
+//! change deadlines. Each operation runs under one lock, which stands in for the transactions.
+
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use chrono::{Local, NaiveDateTime};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use crate::audit::{AuditAction, AuditContext};
+use crate::config::{BookingWindow, ChangePolicy};
+use crate::db::{BulkDeleteOutcome, MovieFilter, MovieSort, ReservationError, ReservationPeriod, ScreeningFilter};
+use crate::events::DomainEvent;
+use crate::models::{
+    Movie, NewReservation, Reservation, ReservationDetail, ReservationStatus, Room, Schedule, ScheduleDisplayInfo,
+    User,
+};
+use crate::repositories::{
+    MovieRepository, RepoResult, ReservationRepository, RoomRepository, ScheduleRepository, UserRepository,
+};
+
+/// All the data of the in-memory repositories, plus the audit entries and domain events their
+/// operations produced, so that tests can check those too.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<Data>,
+}
+
+#[derive(Default)]
+struct Data {
+    movies: Vec<Movie>,
+    rooms: Vec<Room>,
+    schedules: Vec<StoredSchedule>,
+    users: Vec<User>,
+    reservations: Vec<Reservation>,
+    audit_log: Vec<(AuditAction, AuditContext)>,
+    events: Vec<DomainEvent>,
+}
+
+struct StoredSchedule {
+    schedule: Schedule,
+    cancelled: bool,
+}
+
+/// A unique violation like the one MySQL reports for `constraint`.
+fn unique_violation(constraint: &str) -> DieselError {
+    DieselError::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new(format!("Duplicate entry for key '{constraint}'")),
+    )
+}
+
+fn next_id(len: usize) -> i32 {
+    len as i32 + 1
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Data> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn add_movie(&self, title: &str, year: i32, director: &str) -> Movie {
+        let mut data = self.lock();
+        let movie = Movie {
+            id: next_id(data.movies.len()),
+            title: title.to_string(),
+            year,
+            director: director.to_string(),
+            poster: None,
+        };
+        data.movies.push(movie.clone());
+        movie
+    }
+
+    pub fn add_room(&self, label: &str, capacity: i32) -> Room {
+        let mut data = self.lock();
+        let room = Room { id: next_id(data.rooms.len()), capacity, label: label.to_string() };
+        data.rooms.push(room.clone());
+        room
+    }
+
+    /// Adds a screening of `movie_id` in `room_id`, which both must exist.
+    pub fn add_schedule(&self, movie_id: i32, room_id: i32, date: NaiveDateTime) -> Schedule {
+        let mut data = self.lock();
+        assert!(data.movie(movie_id).is_some(), "no movie {movie_id}");
+        assert!(data.room(room_id).is_some(), "no room {room_id}");
+        let schedule = Schedule {
+            id: next_id(data.schedules.len()),
+            movie_id,
+            room_id,
+            date,
+            change_cutoff_minutes: None,
+        };
+        data.schedules.push(StoredSchedule { schedule: schedule.clone(), cancelled: false });
+        schedule
+    }
+
+    pub fn cancel_schedule(&self, schedule_id: i32) {
+        let mut data = self.lock();
+        if let Some(stored) = data.schedules.iter_mut().find(|stored| stored.schedule.id == schedule_id) {
+            stored.cancelled = true;
+        }
+    }
+
+    /// Adds an account directly, without the audit entry and event of a registration.
+    pub fn add_user(&self, email: &str, password_hash: &str, is_staff: bool) -> User {
+        let mut data = self.lock();
+        assert!(data.user_by_email(email).is_none(), "{email} is taken");
+        let user = User {
+            id: next_id(data.users.len()),
+            email: email.to_string(),
+            password: password_hash.to_string(),
+            is_staff,
+        };
+        data.users.push(user.clone());
+        user
+    }
+
+    /// Every reservation, whatever its status, in the order they were made.
+    pub fn reservations(&self) -> Vec<Reservation> {
+        self.lock().reservations.clone()
+    }
+
+    /// Number of active reservations for `schedule_id`.
+    pub fn active_reservations(&self, schedule_id: i32) -> usize {
+        self.lock().active_count(schedule_id)
+    }
+
+    pub fn audit_actions(&self) -> Vec<AuditAction> {
+        self.lock().audit_log.iter().map(|(action, _)| *action).collect()
+    }
+
+    pub fn events(&self) -> Vec<DomainEvent> {
+        self.lock().events.clone()
+    }
+}
+
+impl Data {
+    fn movie(&self, id: i32) -> Option<&Movie> {
+        self.movies.iter().find(|movie| movie.id == id)
+    }
+
+    fn room(&self, id: i32) -> Option<&Room> {
+        self.rooms.iter().find(|room| room.id == id)
+    }
+
+    fn schedule(&self, id: i32) -> Option<&StoredSchedule> {
+        self.schedules.iter().find(|stored| stored.schedule.id == id)
+    }
+
+    fn user(&self, id: i32) -> Option<&User> {
+        self.users.iter().find(|user| user.id == id)
+    }
+
+    fn user_by_email(&self, email: &str) -> Option<&User> {
+        // MySQL compares the column case-insensitively.
+        self.users.iter().find(|user| user.email.eq_ignore_ascii_case(email))
+    }
+
+    fn active_reservation(&self, id: i32) -> Option<&Reservation> {
+        self.reservations
+            .iter()
+            .find(|reservation| reservation.id == id && reservation.status == ReservationStatus::Active)
+    }
+
+    fn active_count(&self, schedule_id: i32) -> usize {
+        self.reservations
+            .iter()
+            .filter(|reservation| reservation.schedule_id == schedule_id)
+            .filter(|reservation| reservation.status == ReservationStatus::Active)
+            .count()
+    }
+
+    fn record(&mut self, audit: &AuditContext, action: AuditAction) {
+        self.audit_log.push((action, audit.clone()));
+    }
+
+    fn check_booking_window(&self, schedule_id: i32, window: &BookingWindow) -> Result<(), ReservationError> {
+        let stored = self.schedule(schedule_id).ok_or(DieselError::NotFound)?;
+        let start = stored.schedule.date;
+        let now = Local::now().naive_local();
+
+        if stored.cancelled {
+            Err(ReservationError::ScreeningCancelled)
+        } else if now < window.opens_at(start) {
+            Err(ReservationError::BookingNotOpen(window.opens_at(start)))
+        } else if now > window.closes_at(start) {
+            Err(ReservationError::BookingClosed(window.closes_at(start)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Change deadline of reservation `id`, if it is active.
+    fn change_deadline(&self, id: i32, policy: &ChangePolicy) -> Option<NaiveDateTime> {
+        let reservation = self.active_reservation(id)?;
+        let schedule = &self.schedule(reservation.schedule_id)?.schedule;
+        Some(policy.deadline(schedule.date, schedule.change_cutoff_minutes))
+    }
+
+    fn check_change_deadline(&self, id: i32, policy: &ChangePolicy) -> Result<(), ReservationError> {
+        match self.change_deadline(id, policy) {
+            None => Err(DieselError::NotFound.into()),
+            Some(deadline) if Local::now().naive_local() > deadline => Err(ReservationError::ChangesClosed(deadline)),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Fails the way inserting an active reservation of `user_id` for `schedule_id` would.
+    fn check_seat(&self, user_id: i32, schedule_id: i32) -> Result<(), ReservationError> {
+        let taken = self.reservations.iter().any(|reservation| {
+            reservation.user_id == user_id
+                && reservation.schedule_id == schedule_id
+                && reservation.status == ReservationStatus::Active
+        });
+        if taken {
+            return Err(unique_violation("unique_active_user_schedule").into());
+        }
+
+        let stored = self.schedule(schedule_id).ok_or(DieselError::NotFound)?;
+        let room = self.room(stored.schedule.room_id).ok_or(DieselError::NotFound)?;
+        if self.active_count(schedule_id) >= room.capacity as usize {
+            return Err(ReservationError::CapacityExceeded(schedule_id));
+        }
+        Ok(())
+    }
+
+    fn insert_reservation(&mut self, new_reservation: NewReservation) -> Reservation {
+        let now = Local::now().naive_local();
+        let reservation = Reservation {
+            id: next_id(self.reservations.len()),
+            user_id: new_reservation.user_id,
+            schedule_id: new_reservation.schedule_id,
+            status: ReservationStatus::Active,
+            created_at: now,
+            updated_at: now,
+            changed_by: new_reservation.changed_by,
+            moved_from_id: new_reservation.moved_from_id,
+        };
+        self.reservations.push(reservation.clone());
+        reservation
+    }
+
+    fn set_status(&mut self, id: i32, status: ReservationStatus, changed_by: Option<i32>) -> Option<Reservation> {
+        let reservation = self.reservations.iter_mut().find(|reservation| reservation.id == id)?;
+        reservation.status = status;
+        reservation.changed_by = changed_by;
+        reservation.updated_at = Local::now().naive_local();
+        Some(reservation.clone())
+    }
+
+    fn screenings(&self, filter: &ScreeningFilter) -> Vec<ScheduleDisplayInfo> {
+        let mut screenings: Vec<ScheduleDisplayInfo> = self
+            .schedules
+            .iter()
+            .filter(|stored| !stored.cancelled)
+            .map(|stored| &stored.schedule)
+            .filter(|schedule| filter.movie_id.is_none_or(|movie_id| schedule.movie_id == movie_id))
+            .filter(|schedule| filter.from.is_none_or(|from| schedule.date >= from))
+            .filter(|schedule| filter.to.is_none_or(|to| schedule.date < to))
+            .filter(|schedule| filter.schedule_ids.as_ref().is_none_or(|ids| ids.contains(&schedule.id)))
+            .filter_map(|schedule| {
+                let movie = self.movie(schedule.movie_id)?.clone();
+                let room = self.room(schedule.room_id)?.clone();
+                Some(ScheduleDisplayInfo {
+                    available_seats: room.capacity - self.active_count(schedule.id) as i32,
+                    schedule: schedule.clone(),
+                    movie,
+                    room,
+                })
+            })
+            .collect();
+        screenings.sort_by_key(|info| (info.schedule.date, info.schedule.id));
+        screenings
+    }
+
+    fn matches(&self, movie: &Movie, filter: &MovieFilter) -> bool {
+        let starts_with = |value: &str, prefix: &str| value.to_lowercase().starts_with(&prefix.to_lowercase());
+        filter
+            .search
+            .as_deref()
+            .is_none_or(|search| starts_with(&movie.title, search) || starts_with(&movie.director, search))
+            && filter.year_from.is_none_or(|year_from| movie.year >= year_from)
+            && filter.year_to.is_none_or(|year_to| movie.year <= year_to)
+            && filter.showing_between.is_none_or(|(from, to)| {
+                self.schedules.iter().any(|stored| {
+                    stored.schedule.movie_id == movie.id
+                        && stored.schedule.date >= from
+                        && stored.schedule.date < to
+                        && !stored.cancelled
+                })
+            })
+    }
+}
+
+#[async_trait]
+impl MovieRepository for MemoryStore {
+    async fn find(&self, id: i32) -> RepoResult<Movie> {
+        Ok(self.lock().movie(id).cloned().ok_or(DieselError::NotFound))
+    }
+
+    async fn all(&self) -> RepoResult<Vec<Movie>> {
+        Ok(Ok(self.lock().movies.clone()))
+    }
+
+    async fn search(&self, filter: MovieFilter, limit: i64, offset: i64) -> RepoResult<(Vec<Movie>, i64)> {
+        let data = self.lock();
+        let mut movies: Vec<Movie> = data.movies.iter().filter(|movie| data.matches(movie, &filter)).cloned().collect();
+        let title = |movie: &Movie| movie.title.to_lowercase();
+        match filter.sort {
+            MovieSort::TitleAsc => movies.sort_by_key(|movie| (title(movie), movie.id)),
+            MovieSort::TitleDesc => movies.sort_by_key(|movie| std::cmp::Reverse((title(movie), movie.id))),
+            MovieSort::YearAsc => movies.sort_by_key(|movie| (movie.year, title(movie))),
+            MovieSort::YearDesc => movies.sort_by_key(|movie| (std::cmp::Reverse(movie.year), title(movie))),
+        }
+
+        let total = movies.len() as i64;
+        let page = movies.into_iter().skip(offset.max(0) as usize).take(limit.max(0) as usize).collect();
+        Ok(Ok((page, total)))
+    }
+
+    async fn set_poster(&self, movie_id: i32, poster: String, audit: AuditContext) -> RepoResult<Option<String>> {
+        let mut data = self.lock();
+        let Some(movie) = data.movies.iter_mut().find(|movie| movie.id == movie_id) else {
+            return Ok(Err(DieselError::NotFound));
+        };
+        let previous = movie.poster.replace(poster);
+        data.record(&audit, AuditAction::PosterChanged);
+        data.events.push(DomainEvent::CatalogueChanged);
+        Ok(Ok(previous))
+    }
+}
+
+#[async_trait]
+impl ScheduleRepository for MemoryStore {
+    async fn screenings(&self, filter: ScreeningFilter) -> RepoResult<Vec<ScheduleDisplayInfo>> {
+        Ok(Ok(self.lock().screenings(&filter)))
+    }
+
+    async fn live_screenings(&self, filter: ScreeningFilter) -> RepoResult<Vec<ScheduleDisplayInfo>> {
+        Ok(Ok(self.lock().screenings(&filter)))
+    }
+}
+
+#[async_trait]
+impl RoomRepository for MemoryStore {
+    async fn find(&self, id: i32) -> RepoResult<Room> {
+        Ok(self.lock().room(id).cloned().ok_or(DieselError::NotFound))
+    }
+
+    async fn all(&self) -> RepoResult<Vec<Room>> {
+        Ok(Ok(self.lock().rooms.clone()))
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryStore {
+    async fn find(&self, id: i32) -> RepoResult<Option<User>> {
+        Ok(Ok(self.lock().user(id).cloned()))
+    }
+
+    async fn find_by_email(&self, email: String) -> RepoResult<Option<User>> {
+        Ok(Ok(self.lock().user_by_email(&email).cloned()))
+    }
+
+    async fn register(&self, email: String, password_hash: String, ip_address: Option<String>) -> RepoResult<i32> {
+        let mut data = self.lock();
+        if data.user_by_email(&email).is_some() {
+            return Ok(Err(unique_violation("unique_email")));
+        }
+        let user_id = next_id(data.users.len());
+        data.users.push(User { id: user_id, email: email.clone(), password: password_hash, is_staff: false });
+        data.record(&AuditContext::new(Some(user_id), ip_address), AuditAction::Register);
+        data.events.push(DomainEvent::UserRegistered { user_id, email });
+        Ok(Ok(user_id))
+    }
+
+    async fn record_login(&self, _email: String, audit: AuditContext, succeeded: bool) -> RepoResult<()> {
+        let action = if succeeded { AuditAction::Login } else { AuditAction::LoginFailed };
+        self.lock().record(&audit, action);
+        Ok(Ok(()))
+    }
+}
+
+#[async_trait]
+impl ReservationRepository for MemoryStore {
+    async fn create(
+        &self,
+        new_reservation: NewReservation,
+        window: BookingWindow,
+        audit: AuditContext,
+    ) -> RepoResult<i32, ReservationError> {
+        let mut data = self.lock();
+        let checked = data
+            .check_booking_window(new_reservation.schedule_id, &window)
+            .and_then(|()| data.check_seat(new_reservation.user_id, new_reservation.schedule_id));
+        if let Err(e) = checked {
+            return Ok(Err(e));
+        }
+
+        let created = data.insert_reservation(new_reservation);
+        data.record(&audit, AuditAction::ReservationCreated);
+        data.events.push(DomainEvent::ReservationCreated {
+            reservation_id: created.id,
+            user_id: created.user_id,
+            schedule_id: created.schedule_id,
+        });
+        Ok(Ok(created.id))
+    }
+
+    async fn find_active(&self, id: i32) -> RepoResult<Reservation> {
+        Ok(self.lock().active_reservation(id).cloned().ok_or(DieselError::NotFound))
+    }
+
+    async fn list_for_user(
+        &self,
+        user_id: i32,
+        period: ReservationPeriod,
+        after: Option<(NaiveDateTime, i32)>,
+        limit: i64,
+    ) -> RepoResult<Vec<ReservationDetail>> {
+        let data = self.lock();
+        let now = Local::now().naive_local();
+        let mut details: Vec<ReservationDetail> = data
+            .reservations
+            .iter()
+            .filter(|reservation| reservation.user_id == user_id)
+            .filter_map(|reservation| {
+                let schedule = &data.schedule(reservation.schedule_id)?.schedule;
+                let upcoming = reservation.status == ReservationStatus::Active && schedule.date >= now;
+                if upcoming != (period == ReservationPeriod::Upcoming) {
+                    return None;
+                }
+                Some(ReservationDetail {
+                    reservation_id: reservation.id,
+                    user_email: data.users.iter().find(|user| user.id == user_id)?.email.clone(),
+                    movie_title: data.movie(schedule.movie_id)?.title.clone(),
+                    room_label: data.room(schedule.room_id)?.label.clone(),
+                    schedule_date: schedule.date,
+                    status: reservation.status,
+                    updated_at: reservation.updated_at,
+                })
+            })
+            .filter(|detail| {
+                let key = (detail.schedule_date, detail.reservation_id);
+                match period {
+                    ReservationPeriod::Upcoming => after.is_none_or(|after| key > after),
+                    ReservationPeriod::History => after.is_none_or(|after| key < after),
+                }
+            })
+            .collect();
+        details.sort_by_key(|detail| (detail.schedule_date, detail.reservation_id));
+        if period == ReservationPeriod::History {
+            details.reverse();
+        }
+        details.truncate(limit.max(0) as usize);
+        Ok(Ok(details))
+    }
+
+    async fn belong_to_user(&self, ids: Vec<i32>, user_id: i32) -> RepoResult<bool> {
+        let data = self.lock();
+        let ids: HashSet<i32> = ids.into_iter().collect();
+        let owned = ids
+            .iter()
+            .filter(|id| data.active_reservation(**id).is_some_and(|reservation| reservation.user_id == user_id))
+            .count();
+        Ok(Ok(owned == ids.len()))
+    }
+
+    async fn move_to(
+        &self,
+        id: i32,
+        schedule_id: i32,
+        window: BookingWindow,
+        policy: ChangePolicy,
+        audit: AuditContext,
+    ) -> RepoResult<Reservation, ReservationError> {
+        let mut data = self.lock();
+        if let Err(e) = data.check_change_deadline(id, &policy) {
+            return Ok(Err(e));
+        }
+        let Some(current) = data.active_reservation(id).cloned() else {
+            return Ok(Err(DieselError::NotFound.into()));
+        };
+        if current.schedule_id == schedule_id {
+            return Ok(Ok(current));
+        }
+        let checked = data
+            .check_booking_window(schedule_id, &window)
+            .and_then(|()| data.check_seat(current.user_id, schedule_id));
+        if let Err(e) = checked {
+            return Ok(Err(e));
+        }
+
+        data.set_status(id, ReservationStatus::Moved, audit.user_id);
+        let moved = data.insert_reservation(NewReservation {
+            user_id: current.user_id,
+            schedule_id,
+            changed_by: audit.user_id,
+            moved_from_id: Some(id),
+        });
+        data.record(&audit, AuditAction::ReservationMoved);
+        data.events.push(DomainEvent::ReservationMoved {
+            reservation_id: moved.id,
+            moved_from_id: id,
+            user_id: moved.user_id,
+            from_schedule_id: current.schedule_id,
+            to_schedule_id: moved.schedule_id,
+        });
+        Ok(Ok(moved))
+    }
+
+    async fn cancel(&self, id: i32, policy: ChangePolicy, audit: AuditContext) -> RepoResult<usize, ReservationError> {
+        let mut data = self.lock();
+        if let Err(e) = data.check_change_deadline(id, &policy) {
+            return Ok(Err(e));
+        }
+        let Some(cancelled) = data.set_status(id, ReservationStatus::Cancelled, audit.user_id) else {
+            return Ok(Err(DieselError::NotFound.into()));
+        };
+        data.record(&audit, AuditAction::ReservationCancelled);
+        data.events.push(DomainEvent::ReservationCancelled {
+            reservation_id: id,
+            user_id: cancelled.user_id,
+            schedule_id: cancelled.schedule_id,
+            cancelled_by: audit.user_id,
+        });
+        Ok(Ok(1))
+    }
+
+    async fn cancel_many(
+        &self,
+        ids: Vec<i32>,
+        policy: ChangePolicy,
+        audit: AuditContext,
+    ) -> RepoResult<BulkDeleteOutcome> {
+        let mut data = self.lock();
+        let now = Local::now().naive_local();
+        let mut seen = HashSet::new();
+        let (deletable, refused): (Vec<_>, Vec<_>) = ids
+            .iter()
+            .filter(|id| seen.insert(**id))
+            .filter_map(|id| data.change_deadline(*id, &policy).map(|deadline| (*id, deadline)))
+            .partition(|(_, deadline)| now <= *deadline);
+        let deleted: Vec<i32> = deletable.into_iter().map(|(id, _)| id).collect();
+
+        for id in &deleted {
+            if let Some(cancelled) = data.set_status(*id, ReservationStatus::Cancelled, audit.user_id) {
+                data.events.push(DomainEvent::ReservationCancelled {
+                    reservation_id: cancelled.id,
+                    user_id: cancelled.user_id,
+                    schedule_id: cancelled.schedule_id,
+                    cancelled_by: audit.user_id,
+                });
+            }
+        }
+        data.record(&audit, AuditAction::ReservationsBulkCancelled);
+        Ok(Ok(BulkDeleteOutcome { deleted, refused }))
+    }
+}
@@ -0,0 +1,147 @@
+//! Data access behind traits, so that handlers work the same against MySQL and against the
+//! in-memory store used by tests.
+//!
+//! Every method returns what [`db::run`](crate::db::run) does: the outer error is a failure to
+//! reach the database at all, the inner one the operation's own.
+
+pub mod memory;
+pub mod mysql;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use crate::audit::AuditContext;
+use crate::config::{BookingWindow, ChangePolicy};
+use crate::db::{
+    BlockingError, BulkDeleteOutcome, DbPools, MovieFilter, ReservationError, ReservationPeriod, ScreeningFilter,
+};
+use crate::models::{Movie, NewReservation, Reservation, ReservationDetail, Room, ScheduleDisplayInfo, User};
+
+pub type RepoResult<T, E = diesel::result::Error> = Result<Result<T, E>, BlockingError>;
+
+#[async_trait]
+pub trait MovieRepository: Send + Sync {
+    /// Fails with `NotFound` if there is no movie `id`.
+    async fn find(&self, id: i32) -> RepoResult<Movie>;
+
+    async fn all(&self) -> RepoResult<Vec<Movie>>;
+
+    /// One page of movies matching `filter` and the total number of matches.
+    async fn search(&self, filter: MovieFilter, limit: i64, offset: i64) -> RepoResult<(Vec<Movie>, i64)>;
+
+    /// Points the poster of `movie_id` at `poster` and returns the one it replaces.
+    async fn set_poster(&self, movie_id: i32, poster: String, audit: AuditContext) -> RepoResult<Option<String>>;
+}
+
+#[async_trait]
+pub trait ScheduleRepository: Send + Sync {
+    /// Screenings matching `filter` with their seat counts, which may lag behind bookings.
+    async fn screenings(&self, filter: ScreeningFilter) -> RepoResult<Vec<ScheduleDisplayInfo>>;
+
+    /// Like [`screenings`](Self::screenings), but with seat counts that include every booking
+    /// made so far, for the forms that make new ones.
+    async fn live_screenings(&self, filter: ScreeningFilter) -> RepoResult<Vec<ScheduleDisplayInfo>>;
+}
+
+#[async_trait]
+pub trait RoomRepository: Send + Sync {
+    /// Fails with `NotFound` if there is no room `id`.
+    async fn find(&self, id: i32) -> RepoResult<Room>;
+
+    async fn all(&self) -> RepoResult<Vec<Room>>;
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    /// The account a session belongs to, or `None` if it no longer exists.
+    async fn find(&self, id: i32) -> RepoResult<Option<User>>;
+
+    async fn find_by_email(&self, email: String) -> RepoResult<Option<User>>;
+
+    /// Creates an account and returns its id. Fails with a unique violation if `email` is taken.
+    async fn register(&self, email: String, password_hash: String, ip_address: Option<String>) -> RepoResult<i32>;
+
+    /// Audits a login attempt for `email`, against `audit.user_id` if the account exists.
+    async fn record_login(&self, email: String, audit: AuditContext, succeeded: bool) -> RepoResult<()>;
+}
+
+#[async_trait]
+pub trait ReservationRepository: Send + Sync {
+    /// Books a seat and returns the reservation id. Fails with a unique violation if the user
+    /// already holds an active reservation for the schedule.
+    async fn create(
+        &self,
+        new_reservation: NewReservation,
+        window: BookingWindow,
+        audit: AuditContext,
+    ) -> RepoResult<i32, ReservationError>;
+
+    /// Fails with `NotFound` unless reservation `id` exists and is active.
+    async fn find_active(&self, id: i32) -> RepoResult<Reservation>;
+
+    /// Up to `limit` of the user's reservations for `period`, continuing after the
+    /// `(schedule_date, reservation_id)` of the last row of the previous page.
+    async fn list_for_user(
+        &self,
+        user_id: i32,
+        period: ReservationPeriod,
+        after: Option<(NaiveDateTime, i32)>,
+        limit: i64,
+    ) -> RepoResult<Vec<ReservationDetail>>;
+
+    /// Whether all of `ids` are active reservations of `user_id`.
+    async fn belong_to_user(&self, ids: Vec<i32>, user_id: i32) -> RepoResult<bool>;
+
+    /// Moves reservation `id` to `schedule_id` and returns the reservation replacing it.
+    async fn move_to(
+        &self,
+        id: i32,
+        schedule_id: i32,
+        window: BookingWindow,
+        policy: ChangePolicy,
+        audit: AuditContext,
+    ) -> RepoResult<Reservation, ReservationError>;
+
+    async fn cancel(&self, id: i32, policy: ChangePolicy, audit: AuditContext) -> RepoResult<usize, ReservationError>;
+
+    /// Cancels those of `ids` still before their change deadline and reports the rest.
+    async fn cancel_many(&self, ids: Vec<i32>, policy: ChangePolicy, audit: AuditContext)
+        -> RepoResult<BulkDeleteOutcome>;
+}
+
+/// One implementation of each repository, as kept in the application state.
+#[derive(Clone)]
+pub struct Repositories {
+    pub movies: Arc<dyn MovieRepository>,
+    pub schedules: Arc<dyn ScheduleRepository>,
+    pub rooms: Arc<dyn RoomRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub reservations: Arc<dyn ReservationRepository>,
+}
+
+impl Repositories {
+    /// Repositories backed by the MySQL cluster behind `pools`.
+    pub fn mysql(pools: Arc<DbPools>) -> Self {
+        let repository = Arc::new(mysql::MysqlRepository::new(pools));
+        Repositories {
+            movies: repository.clone(),
+            schedules: repository.clone(),
+            rooms: repository.clone(),
+            users: repository.clone(),
+            reservations: repository,
+        }
+    }
+
+    /// Repositories sharing the data of `store`, which tests can seed and inspect.
+    pub fn in_memory(store: Arc<memory::MemoryStore>) -> Self {
+        Repositories {
+            movies: store.clone(),
+            schedules: store.clone(),
+            rooms: store.clone(),
+            users: store.clone(),
+            reservations: store,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! The repositories of the running application, which delegate to the queries in [`db`] on the
+//! primary or on a read replica.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde_json::json;
+
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::config::{BookingWindow, ChangePolicy};
+use crate::db::{self, BulkDeleteOutcome, DbPools, MovieFilter, ReservationError, ReservationPeriod, ScreeningFilter};
+use crate::models::{Movie, NewReservation, Reservation, ReservationDetail, Room, ScheduleDisplayInfo, User};
+use crate::repositories::{
+    MovieRepository, RepoResult, ReservationRepository, RoomRepository, ScheduleRepository, UserRepository,
+};
+
+/// Reads that may be slightly stale go to the replicas; everything else to the primary.
+pub struct MysqlRepository {
+    pools: Arc<DbPools>,
+}
+
+impl MysqlRepository {
+    pub fn new(pools: Arc<DbPools>) -> Self {
+        MysqlRepository { pools }
+    }
+}
+
+#[async_trait]
+impl MovieRepository for MysqlRepository {
+    async fn find(&self, id: i32) -> RepoResult<Movie> {
+        db::run_read(&self.pools, move |conn| db::get_movie_by_id(conn, id)).await
+    }
+
+    async fn all(&self) -> RepoResult<Vec<Movie>> {
+        db::run_read(&self.pools, db::get_all_movies).await
+    }
+
+    async fn search(&self, filter: MovieFilter, limit: i64, offset: i64) -> RepoResult<(Vec<Movie>, i64)> {
+        db::run_read(&self.pools, move |conn| db::search_movies(conn, &filter, limit, offset)).await
+    }
+
+    async fn set_poster(&self, movie_id: i32, poster: String, audit: AuditContext) -> RepoResult<Option<String>> {
+        db::run(self.pools.primary(), move |conn| db::set_movie_poster(conn, movie_id, &poster, &audit)).await
+    }
+}
+
+#[async_trait]
+impl ScheduleRepository for MysqlRepository {
+    async fn screenings(&self, filter: ScreeningFilter) -> RepoResult<Vec<ScheduleDisplayInfo>> {
+        db::run_read(&self.pools, move |conn| db::get_screenings(conn, &filter)).await
+    }
+
+    async fn live_screenings(&self, filter: ScreeningFilter) -> RepoResult<Vec<ScheduleDisplayInfo>> {
+        db::run(self.pools.primary(), move |conn| db::get_screenings(conn, &filter)).await
+    }
+}
+
+#[async_trait]
+impl RoomRepository for MysqlRepository {
+    async fn find(&self, id: i32) -> RepoResult<Room> {
+        db::run_read(&self.pools, move |conn| db::get_room_by_id(conn, id)).await
+    }
+
+    async fn all(&self) -> RepoResult<Vec<Room>> {
+        db::run_read(&self.pools, db::get_all_rooms).await
+    }
+}
+
+#[async_trait]
+impl UserRepository for MysqlRepository {
+    // On the primary, so that a change to an account, such as being made staff, applies to the
+    // next request.
+    async fn find(&self, id: i32) -> RepoResult<Option<User>> {
+        db::run(self.pools.primary(), move |conn| db::get_user_by_id(conn, id)).await
+    }
+
+    // On the primary, so that an account can sign in as soon as it is registered.
+    async fn find_by_email(&self, email: String) -> RepoResult<Option<User>> {
+        db::run(self.pools.primary(), move |conn| db::get_user_by_email(conn, &email)).await
+    }
+
+    async fn register(&self, email: String, password_hash: String, ip_address: Option<String>) -> RepoResult<i32> {
+        db::run(self.pools.primary(), move |conn| db::create_user(conn, &email, &password_hash, ip_address)).await
+    }
+
+    async fn record_login(&self, email: String, audit: AuditContext, succeeded: bool) -> RepoResult<()> {
+        let action = if succeeded { AuditAction::Login } else { AuditAction::LoginFailed };
+        db::run(self.pools.primary(), move |conn| {
+            audit::record(conn, &audit, action, None, None, Some(json!({ "email": email })))
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl ReservationRepository for MysqlRepository {
+    async fn create(
+        &self,
+        new_reservation: NewReservation,
+        window: BookingWindow,
+        audit: AuditContext,
+    ) -> RepoResult<i32, ReservationError> {
+        db::run(self.pools.primary(), move |conn| db::create_reservation(conn, new_reservation, &window, &audit)).await
+    }
+
+    async fn find_active(&self, id: i32) -> RepoResult<Reservation> {
+        db::run(self.pools.primary(), move |conn| db::get_reservation_by_id(conn, id)).await
+    }
+
+    async fn list_for_user(
+        &self,
+        user_id: i32,
+        period: ReservationPeriod,
+        after: Option<(NaiveDateTime, i32)>,
+        limit: i64,
+    ) -> RepoResult<Vec<ReservationDetail>> {
+        db::run(self.pools.primary(), move |conn| {
+            db::get_reservations_with_details(conn, user_id, period, after, limit)
+        })
+        .await
+    }
+
+    async fn belong_to_user(&self, ids: Vec<i32>, user_id: i32) -> RepoResult<bool> {
+        db::run(self.pools.primary(), move |conn| db::check_if_users_reservation(conn, ids, user_id)).await
+    }
+
+    async fn move_to(
+        &self,
+        id: i32,
+        schedule_id: i32,
+        window: BookingWindow,
+        policy: ChangePolicy,
+        audit: AuditContext,
+    ) -> RepoResult<Reservation, ReservationError> {
+        db::run(self.pools.primary(), move |conn| {
+            db::update_reservation(conn, id, schedule_id, &window, &policy, &audit)
+        })
+        .await
+    }
+
+    async fn cancel(&self, id: i32, policy: ChangePolicy, audit: AuditContext) -> RepoResult<usize, ReservationError> {
+        db::run(self.pools.primary(), move |conn| db::delete_reservation(conn, id, &policy, &audit)).await
+    }
+
+    async fn cancel_many(
+        &self,
+        ids: Vec<i32>,
+        policy: ChangePolicy,
+        audit: AuditContext,
+    ) -> RepoResult<BulkDeleteOutcome> {
+        db::run(self.pools.primary(), move |conn| db::delete_multiple_reservations(conn, ids, &policy, &audit)).await
+    }
+}
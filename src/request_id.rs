@@ -0,0 +1,79 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::trace::MakeSpan;
+use tracing::Span;
+use uuid::Uuid;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest request id accepted from nginx or a client; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// Correlation id of a request, taken from `X-Request-Id` or generated, and returned in the
+/// response's `X-Request-Id`.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"-_.:".contains(&byte));
+        valid.then(|| RequestId(value.to_string()))
+    }
+
+    fn generate() -> Self {
+        RequestId(Uuid::new_v4().to_string())
+    }
+
+    /// Id of the request being handled by the current task, if any.
+    pub fn current() -> Option<String> {
+        CURRENT.try_with(|id| id.0.clone()).ok()
+    }
+}
+
+/// Accepts or generates the request id, makes it available to the handler and its spans, and
+/// echoes it back to the client.
+pub async fn propagate(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::generate);
+    request.extensions_mut().insert(id.clone());
+
+    let mut response = CURRENT.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        response.headers_mut().insert(REQUEST_ID_HEADER.clone(), value);
+    }
+    response
+}
+
+/// Names the span `TraceLayer` opens for each request after its request id, so that every log line
+/// of the request, including those of its database work, carries the id.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestSpan;
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &axum::http::Request<B>) -> Span {
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map_or("", |id| id.0.as_str());
+        tracing::info_span!(
+            "request",
+            request_id,
+            method = %request.method(),
+            uri = %request.uri(),
+        )
+    }
+}
@@ -1,41 +1,72 @@
 use axum::{
+    extract::DefaultBodyLimit,
     routing::{get, post, delete},
     Router,
 };
-use std::sync::Arc;
-use crate::db::MysqlPool;
-use crate::handlers::{movies, reservations};
+use crate::state::AppState;
+use crate::handlers::{admin, health, movies, posters, programme, reservations};
 use crate::handlers;
+use crate::http_metrics;
 
-pub fn app_router(pool: Arc<MysqlPool>) -> Router {
+pub fn app_router(state: AppState) -> Router {
     Router::new()
         .route("/", get(handlers::index_handler))
         .nest("/movies", movie_routes())
+        .nest("/posters", poster_routes())
+        .route("/programme", get(programme::programme_handler))
         .nest("/reservations", reservation_routes())
         .merge(auth_routes())
-        .with_state(pool)
+        .nest("/admin", admin_routes(state.config.posters.max_upload_bytes))
+        .with_state(state)
 }
 
-fn movie_routes() -> Router<Arc<MysqlPool>> {
+/// Probes for the load balancer and orchestrator plus the Prometheus scrape endpoint, kept apart so
+/// that they can be mounted outside the session and trace layers.
+pub fn health_router(state: AppState) -> Router {
+    Router::new()
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(http_metrics::metrics_handler))
+        .with_state(state)
+}
+
+fn movie_routes() -> Router<AppState> {
     Router::new()
         .route("/", get(movies::movies_handler))
         .route("/{movie_id}", get(movies::movie_handler))
 }
 
-fn auth_routes() -> Router<Arc<MysqlPool>> {
+fn auth_routes() -> Router<AppState> {
     Router::new()
         .route("/register", get(handlers::auth::show_register).post(handlers::auth::handle_register))
         .route("/login", get(handlers::auth::show_login).post(handlers::auth::handle_login))
         .route("/logout", get(handlers::auth::logout))
 }
 
-fn reservation_routes() -> Router<Arc<MysqlPool>> {
+fn reservation_routes() -> Router<AppState> {
     Router::new()
         .route("/", get(reservations::list_reservations_handler))
         .route("/new", get(reservations::show_create_reservation_form))
+        .route("/availability", get(reservations::availability_events))
         .route("/", post(reservations::create_reservation))
         .route("/edit/{id}", get(reservations::show_update_reservation_form))
         .route("/{id}", post(reservations::update_reservation))
         .route("/{id}", delete(reservations::delete_reservation))
         .route("/bulk_delete", post(reservations::delete_multiple_reservations))
 }
+
+fn poster_routes() -> Router<AppState> {
+    Router::new()
+        .route("/placeholder.svg", get(posters::placeholder_handler))
+        .route("/{id}/{file}", get(posters::poster_handler))
+}
+
+fn admin_routes(max_upload_bytes: usize) -> Router<AppState> {
+    Router::new()
+        .route("/audit", get(admin::audit_log_handler))
+        .route("/movies", get(admin::movies_handler))
+        .route(
+            "/movies/{movie_id}/poster",
+            post(admin::upload_poster_handler).layer(DefaultBodyLimit::max(max_upload_bytes)),
+        )
+}
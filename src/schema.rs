@@ -1,5 +1,21 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_log (id) {
+        id -> Bigint,
+        created_at -> Datetime,
+        user_id -> Nullable<Integer>,
+        #[max_length = 32]
+        action -> Varchar,
+        #[max_length = 64]
+        target -> Nullable<Varchar>,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        before_data -> Nullable<Text>,
+        after_data -> Nullable<Text>,
+    }
+}
+
 diesel::table! {                                                                                                                                        
     movies (id) {                                                                                                                                       
         id -> Integer,                                                                                                                                  
@@ -9,15 +25,34 @@ diesel::table! {
         #[max_length = 255]                                                                                                                             
         director -> Varchar,                                                                                                                            
         #[max_length = 255]
-        poster -> Varchar
+        poster -> Nullable<Varchar>,
+        #[max_length = 64]
+        external_id -> Varchar,
     }                                                                                                                                                   
 }
 
+diesel::table! {
+    outbox (id) {
+        id -> Bigint,
+        created_at -> Datetime,
+        #[max_length = 64]
+        event_type -> Varchar,
+        payload -> Text,
+    }
+}
+
 diesel::table! {                                                                                                                                        
     reservation (id) {                                                                                                                                  
         id -> Integer,
         user_id -> Integer,                                                                                                                             
         schedule_id -> Integer,                                                                                                                         
+        #[max_length = 16]
+        status -> Varchar,
+        created_at -> Datetime,
+        updated_at -> Datetime,
+        changed_by -> Nullable<Integer>,
+        moved_from_id -> Nullable<Integer>,
+        active_marker -> Nullable<Tinyint>,
     }                                                                                                                                                   
 }
 
@@ -36,6 +71,17 @@ diesel::table! {
         movie_id -> Integer,                                                                                                                            
         room_id -> Integer,                                                                                                                             
         date -> Datetime,                                                                                                                               
+        change_cutoff_minutes -> Nullable<Integer>,
+        cancelled_at -> Nullable<Datetime>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        #[max_length = 32]
+        id -> Varchar,
+        data -> Text,
+        expiry_date -> Datetime,
     }
 }
 
@@ -46,18 +92,23 @@ diesel::table! {
         email -> Varchar,                                                                                                                               
         #[max_length = 255]                                                                                                                             
         password -> Varchar,                                                                                                                            
+        is_staff -> Bool,
     }
 }
 
+diesel::joinable!(audit_log -> users (user_id));
 diesel::joinable!(reservation -> schedule (schedule_id));
 diesel::joinable!(reservation -> users (user_id));
 diesel::joinable!(schedule -> movies (movie_id));
 diesel::joinable!(schedule -> rooms (room_id));
 
 diesel::allow_tables_to_appear_in_same_query!(                                                                                                          
+    audit_log,
     movies,                                                                                                                                             
+    outbox,
     reservation,
     rooms,                                                                                                                                              
     schedule,                                                                                                                                           
+    sessions,
     users,                                                                                                                                              
 );
\ No newline at end of file
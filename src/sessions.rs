@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::MysqlConnection;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store::{self, ExpiredDeletion, SessionStore};
+
+use crate::db::{self, MysqlPool};
+use crate::models::StoredSession;
+use crate::schema::sessions;
+
+/// Keeps sessions in the `sessions` table, so that every server sees them and a user stays signed
+/// in whichever server the load balancer picks.
+#[derive(Debug, Clone)]
+pub struct MysqlSessionStore {
+    pool: Arc<MysqlPool>,
+}
+
+impl MysqlSessionStore {
+    pub fn new(pool: Arc<MysqlPool>) -> Self {
+        MysqlSessionStore { pool }
+    }
+
+    async fn run<F, T>(&self, f: F) -> session_store::Result<T>
+    where
+        F: FnOnce(&mut MysqlConnection) -> QueryResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        db::run(&self.pool, f)
+            .await
+            .map_err(|e| session_store::Error::Backend(e.to_string()))?
+            .map_err(|e| session_store::Error::Backend(e.to_string()))
+    }
+}
+
+/// The store behind the session layer, which the readiness check probes too. The server puts a
+/// [`MysqlSessionStore`] behind it and the HTTP tests a memory store.
+#[derive(Debug, Clone)]
+pub struct SharedSessionStore(Arc<dyn SessionStore>);
+
+impl SharedSessionStore {
+    pub fn new(store: impl SessionStore) -> Self {
+        SharedSessionStore(Arc::new(store))
+    }
+}
+
+#[async_trait]
+impl SessionStore for SharedSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        self.0.create(record).await
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        self.0.save(record).await
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        self.0.load(session_id).await
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        self.0.delete(session_id).await
+    }
+}
+
+fn to_stored(record: &Record) -> session_store::Result<StoredSession> {
+    let expiry = DateTime::from_timestamp(record.expiry_date.unix_timestamp(), 0)
+        .ok_or_else(|| session_store::Error::Encode(format!("invalid expiry date {}", record.expiry_date)))?;
+    Ok(StoredSession {
+        id: record.id.to_string(),
+        data: serde_json::to_string(record).map_err(|e| session_store::Error::Encode(e.to_string()))?,
+        expiry_date: expiry.with_timezone(&Local).naive_local(),
+    })
+}
+
+fn from_stored(stored: StoredSession) -> session_store::Result<Record> {
+    serde_json::from_str(&stored.data).map_err(|e| session_store::Error::Decode(e.to_string()))
+}
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+#[async_trait]
+impl SessionStore for MysqlSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        // Ids are random 128-bit numbers; on the off chance one is taken, draw another.
+        loop {
+            let stored = to_stored(record)?;
+            let inserted = db::run(&self.pool, move |conn| {
+                diesel::insert_into(sessions::table).values(&stored).execute(conn)
+            })
+            .await
+            .map_err(|e| session_store::Error::Backend(e.to_string()))?;
+
+            match inserted {
+                Ok(_) => return Ok(()),
+                Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => record.id = Id::default(),
+                Err(e) => return Err(session_store::Error::Backend(e.to_string())),
+            }
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let stored = to_stored(record)?;
+        self.run(move |conn| diesel::replace_into(sessions::table).values(&stored).execute(conn))
+            .await
+            .map(|_| ())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let session_id = session_id.to_string();
+        let stored = self
+            .run(move |conn| {
+                sessions::table
+                    .find(session_id)
+                    .filter(sessions::expiry_date.gt(now()))
+                    .select(StoredSession::as_select())
+                    .first(conn)
+                    .optional()
+            })
+            .await?;
+        stored.map(from_stored).transpose()
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        let session_id = session_id.to_string();
+        self.run(move |conn| diesel::delete(sessions::table.find(session_id)).execute(conn))
+            .await
+            .map(|_| ())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for MysqlSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        self.run(|conn| delete_expired_sessions(conn, now())).await.map(|_| ())
+    }
+}
+
+/// Deletes the sessions that expired before `now` (in local time) and returns how many there were.
+pub fn delete_expired_sessions(conn: &mut MysqlConnection, now: NaiveDateTime) -> QueryResult<usize> {
+    diesel::delete(sessions::table.filter(sessions::expiry_date.le(now))).execute(conn)
+}
+
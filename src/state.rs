@@ -0,0 +1,114 @@
+use axum::extract::FromRef;
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::sync::Arc;
+
+use crate::availability::AvailabilityFeed;
+use crate::config::AppConfig;
+use crate::db::{DbPools, MysqlPool};
+use crate::page_cache::PageCaches;
+use crate::posters::PosterStore;
+use crate::repositories::{
+    MovieRepository, Repositories, ReservationRepository, RoomRepository, ScheduleRepository, UserRepository,
+};
+use crate::sessions::SharedSessionStore;
+use crate::shutdown::Shutdown;
+
+/// Shared state of the router. Handlers extract only the parts they need, e.g.
+/// `State<Arc<dyn ReservationRepository>>` for reservations.
+#[derive(Clone)]
+pub struct AppState {
+    pub repos: Repositories,
+    /// For what has no repository: the audit log, health checks and background jobs.
+    pub pools: Arc<DbPools>,
+    pub config: Arc<AppConfig>,
+    pub availability: AvailabilityFeed,
+    pub sessions: SharedSessionStore,
+    pub metrics: PrometheusHandle,
+    pub posters: Arc<dyn PosterStore>,
+    pub caches: Arc<PageCaches>,
+    pub shutdown: Shutdown,
+}
+
+impl FromRef<AppState> for Arc<MysqlPool> {
+    fn from_ref(state: &AppState) -> Self {
+        state.pools.primary().clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<DbPools> {
+    fn from_ref(state: &AppState) -> Self {
+        state.pools.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<AppConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for AvailabilityFeed {
+    fn from_ref(state: &AppState) -> Self {
+        state.availability.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedSessionStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.sessions.clone()
+    }
+}
+
+impl FromRef<AppState> for PrometheusHandle {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics.clone()
+    }
+}
+
+impl FromRef<AppState> for Shutdown {
+    fn from_ref(state: &AppState) -> Self {
+        state.shutdown.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn PosterStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.posters.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<PageCaches> {
+    fn from_ref(state: &AppState) -> Self {
+        state.caches.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn MovieRepository> {
+    fn from_ref(state: &AppState) -> Self {
+        state.repos.movies.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn ScheduleRepository> {
+    fn from_ref(state: &AppState) -> Self {
+        state.repos.schedules.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn RoomRepository> {
+    fn from_ref(state: &AppState) -> Self {
+        state.repos.rooms.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn UserRepository> {
+    fn from_ref(state: &AppState) -> Self {
+        state.repos.users.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn ReservationRepository> {
+    fn from_ref(state: &AppState) -> Self {
+        state.repos.reservations.clone()
+    }
+}
@@ -0,0 +1,73 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer, Registry};
+
+use crate::config::{AppConfig, LogFormat};
+
+/// Service name reported to the collector unless `OTEL_SERVICE_NAME` is set.
+const DEFAULT_SERVICE_NAME: &str = "cinema";
+
+/// Keeps the span exporter alive; call [`Telemetry::shutdown`] before exiting so that buffered
+/// spans are sent.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.map_or(Ok(()), |provider| provider.shutdown()) {
+            tracing::error!("Failed to flush spans: {e}");
+        }
+    }
+}
+
+/// Installs the global subscriber: logs to stdout in the configured format and, when an OTLP
+/// endpoint is configured, spans to that collector.
+pub fn init(config: &AppConfig) -> Result<Telemetry, ExporterBuildError> {
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+    layers.push(match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    });
+
+    let provider = match &config.tracing.otlp_endpoint {
+        Some(endpoint) => {
+            let provider = tracer_provider(endpoint)?;
+            layers.push(
+                tracing_opentelemetry::layer()
+                    .with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+                    .boxed(),
+            );
+            Some(provider)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(LevelFilter::DEBUG)
+        .init();
+    Ok(Telemetry { provider })
+}
+
+fn tracer_provider(endpoint: &str) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name(DEFAULT_SERVICE_NAME);
+    }
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build())
+}
@@ -1,6 +1,23 @@
 use askama::Template;
+use chrono::{NaiveDate, NaiveDateTime};
+use crate::audit::{AuditAction, AuditLogRow};
+use crate::config::BookingWindow;
+use crate::forms::admin::AuditQuery;
+use crate::forms::movies::MovieQuery;
+use crate::handlers::reservations::ReservationListQuery;
 use crate::models::{Movie, ReservationDetail, Reservation, ScheduleDisplayInfo, User};
 
+/// Renders a template inside a `render` span named after it, so that rendering shows up in traces.
+pub trait RenderTraced: Template {
+    fn render_traced(&self) -> askama::Result<String> {
+        let name = std::any::type_name::<Self>().rsplit("::").next().unwrap_or_default();
+        let _span = tracing::info_span!("render", template = name).entered();
+        self.render()
+    }
+}
+
+impl<T: Template> RenderTraced for T {}
+
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
@@ -12,6 +29,8 @@ pub struct IndexTemplate {
 pub struct ReservationsListTemplate {
     pub reservations: Vec<ReservationDetail>,
     pub error_message: Option<String>,
+    pub view: ReservationListQuery,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Template)]
@@ -19,6 +38,24 @@ pub struct ReservationsListTemplate {
 pub struct ReservationFormTemplate {
     pub reservation: Option<Reservation>,
     pub schedules: Vec<ScheduleDisplayInfo>,
+    /// Schedule to preselect when booking straight from a movie page or the programme.
+    pub selected_schedule_id: Option<i32>,
+    /// Rendered as its own page rather than inside the reservations list.
+    pub standalone: bool,
+}
+
+impl ReservationFormTemplate {
+    /// Whether `schedule_id` is the one of the reservation being edited, whose seat is counted as
+    /// available to its holder and so is not updated live.
+    pub fn is_current_schedule(&self, schedule_id: &i32) -> bool {
+        self.reservation.as_ref().is_some_and(|reservation| reservation.schedule_id == *schedule_id)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "schedule_option_label.html")]
+pub struct ScheduleOptionLabelTemplate<'a> {
+    pub schedule_info: &'a ScheduleDisplayInfo,
 }
 
 #[derive(Template)]
@@ -26,16 +63,63 @@ pub struct ReservationFormTemplate {
 pub struct ErrorTemplate {
     pub error_message: String,
     pub debug_info: String,
+    /// Quoted to support so that the request can be found in the logs.
+    pub request_id: Option<String>,
 }
 
 #[derive(Template)]
 #[template(path = "movies.html")]
 pub struct MoviesTemplate {
     pub movies: Vec<Movie>,
+    pub query: MovieQuery,
+    pub total: i64,
+    pub total_pages: i64,
+}
+
+/// The results part of `movies.html`, swapped in by HTMX when the filters change.
+#[derive(Template)]
+#[template(path = "movies_results.html")]
+pub struct MoviesResultsTemplate {
+    pub movies: Vec<Movie>,
+    pub query: MovieQuery,
+    pub total: i64,
+    pub total_pages: i64,
 }
 
 #[derive(Template)]
 #[template(path = "movie.html")]
 pub struct MovieTemplate {
     pub movie: Movie,
+    pub screenings: Vec<ScheduleDisplayInfo>,
+    pub booking_window: BookingWindow,
+    pub now: NaiveDateTime,
+}
+
+#[derive(Template)]
+#[template(path = "programme.html")]
+pub struct ProgrammeTemplate {
+    pub date: NaiveDate,
+    pub previous_date: NaiveDate,
+    pub next_date: NaiveDate,
+    pub movies: Vec<(Movie, Vec<ScheduleDisplayInfo>)>,
+    pub booking_window: BookingWindow,
+    pub now: NaiveDateTime,
+}
+
+#[derive(Template)]
+#[template(path = "admin_audit.html")]
+pub struct AuditLogTemplate {
+    pub entries: Vec<AuditLogRow>,
+    /// Options of the action filter.
+    pub actions: Vec<AuditAction>,
+    pub query: AuditQuery,
+    pub total: i64,
+    pub total_pages: i64,
+}
+
+#[derive(Template)]
+#[template(path = "admin_movies.html")]
+pub struct AdminMoviesTemplate {
+    pub movies: Vec<Movie>,
+    pub max_upload_bytes: usize,
 }
@@ -7,8 +7,8 @@ until mysqladmin ping -h mysqld -u user -ppassword --silent; do
 done
 
 if [ -n "${MIGRATION}" ]; then
-  diesel migration run
-  diesel print-schema
+  exec cargo run --bin Cinema -- migrate
 else
-  cargo run --bin Cinema
+  # exec so that the server receives the SIGTERM sent on docker-compose stop/restart.
+  exec cargo run --bin Cinema
 fi
@@ -0,0 +1,99 @@
+//! Checks reading and writing catalogue files, which needs no database.
+
+use Cinema::catalogue::{self, CatalogueError, CatalogueFormat, CatalogueMovie, RowError};
+
+fn movie(external_id: &str, title: &str, poster: Option<&str>) -> CatalogueMovie {
+    CatalogueMovie {
+        external_id: external_id.to_string(),
+        title: title.to_string(),
+        year: 1927,
+        director: "Fritz Lang".to_string(),
+        poster: poster.map(str::to_string),
+    }
+}
+
+fn parse_csv(csv: &str) -> Result<Vec<CatalogueMovie>, CatalogueError> {
+    catalogue::parse(CatalogueFormat::Csv, csv.as_bytes())
+}
+
+fn row_errors(result: Result<Vec<CatalogueMovie>, CatalogueError>) -> Vec<RowError> {
+    match result {
+        Err(CatalogueError::InvalidRows(errors)) => errors.0,
+        other => panic!("expected invalid rows, got {other:?}"),
+    }
+}
+
+#[test]
+fn every_invalid_row_is_reported_with_its_number() {
+    let csv = "\
+external_id,title,year,director,poster
+tt-1,Metropolis,1927,Fritz Lang,
+tt-2,,1922,F. W. Murnau,
+tt-3,Nosferatu,1700,F. W. Murnau,
+tt-4,M,1931,Fritz Lang,
+tt-5,Sunrise,not a year,F. W. Murnau,
+";
+    let errors = row_errors(parse_csv(csv));
+
+    let rows: Vec<(usize, Option<&str>)> =
+        errors.iter().map(|error| (error.row, error.external_id.as_deref())).collect();
+    assert_eq!(rows, [(2, Some("tt-2")), (3, Some("tt-3")), (5, None)]);
+    assert_eq!(errors[0].message, "title is required");
+    assert_eq!(errors[1].message, "year 1700 is not between 1888 and 2100");
+}
+
+#[test]
+fn fields_longer_than_their_columns_are_rejected() {
+    let long_title = "x".repeat(256);
+    let csv = format!("external_id,title,year,director,poster\ntt-1,{long_title},1927,Fritz Lang,\n");
+    let errors = row_errors(parse_csv(&csv));
+    assert_eq!(errors[0].message, "title is longer than 255 characters");
+}
+
+#[test]
+fn a_repeated_external_id_is_rejected() {
+    let csv = "\
+external_id,title,year,director,poster
+tt-1,Metropolis,1927,Fritz Lang,
+tt-1,M,1931,Fritz Lang,
+";
+    let errors = row_errors(parse_csv(csv));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].row, 2);
+    assert_eq!(errors[0].message, "external_id appears more than once in the file");
+}
+
+#[test]
+fn importing_a_movie_without_a_poster_keeps_its_poster() {
+    let current = movie("tt-1", "Metropolis", Some("/posters/7"));
+
+    let updated = current.updated_by(&movie("tt-1", "Metropolis (restored)", None));
+    assert_eq!(updated, movie("tt-1", "Metropolis (restored)", Some("/posters/7")));
+
+    let updated = current.updated_by(&movie("tt-1", "Metropolis", Some("posters/1/poster.webp")));
+    assert_eq!(updated.poster.as_deref(), Some("posters/1/poster.webp"), "a new poster replaces it");
+}
+
+#[test]
+fn exported_catalogues_read_back_unchanged() {
+    let movies = vec![
+        movie("tt-1", "Metropolis", Some("posters/1/poster.webp")),
+        movie("tt-2", "M, eine Stadt sucht einen Mörder", None),
+    ];
+
+    for format in [CatalogueFormat::Csv, CatalogueFormat::Json] {
+        let mut file = Vec::new();
+        catalogue::write(format, &movies, &mut file).unwrap();
+        let read_back = catalogue::parse(format, file.as_slice()).unwrap();
+        assert_eq!(read_back, movies, "{format:?}");
+    }
+}
+
+#[test]
+fn an_empty_catalogue_reads_back_empty() {
+    for format in [CatalogueFormat::Csv, CatalogueFormat::Json] {
+        let mut file = Vec::new();
+        catalogue::write(format, &[], &mut file).unwrap();
+        assert!(catalogue::parse(format, file.as_slice()).unwrap().is_empty(), "{format:?}");
+    }
+}
@@ -0,0 +1,39 @@
+//! Checks which address the audit log records for a request behind the load balancer.
+
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::Request;
+
+use Cinema::extractors::client_ip::ClientIp;
+
+async fn client_ip(headers: &[(&str, &str)]) -> Option<String> {
+    let mut request = Request::builder();
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let (mut parts, ()) = request.body(()).unwrap().into_parts();
+    parts.extensions.insert(ConnectInfo("10.0.0.2:51000".parse::<SocketAddr>().unwrap()));
+    let ClientIp(ip) = ClientIp::from_request_parts(&mut parts, &()).await.unwrap();
+    ip
+}
+
+#[tokio::test]
+async fn the_address_set_by_the_load_balancer_wins() {
+    let ip = client_ip(&[("x-real-ip", "203.0.113.7"), ("x-forwarded-for", "198.51.100.1, 203.0.113.7")]).await;
+    assert_eq!(ip.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn addresses_sent_by_the_client_are_not_trusted() {
+    // nginx appends the address it saw to whatever the client sent.
+    let ip = client_ip(&[("x-forwarded-for", "198.51.100.1, 203.0.113.7")]).await;
+    assert_eq!(ip.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn values_that_are_not_addresses_are_ignored() {
+    let long = "x".repeat(100);
+    let ip = client_ip(&[("x-real-ip", long.as_str()), ("x-forwarded-for", "unknown")]).await;
+    assert_eq!(ip.as_deref(), Some("10.0.0.2"), "falls back to the peer address");
+}
@@ -0,0 +1,351 @@
+//! Drives the router the server runs, over HTTP requests, against the in-memory repositories and
+//! session store, so that it needs no database.
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::MysqlConnection;
+use http_body_util::BodyExt;
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tower::ServiceExt;
+use tower_sessions::{MemoryStore as SessionMemoryStore, SessionManagerLayer, SessionStore};
+
+use Cinema::availability::AvailabilityFeed;
+use Cinema::config::{AppConfig, CacheSettings};
+use Cinema::db::DbPools;
+use Cinema::models::{ReservationStatus, User};
+use Cinema::page_cache::PageCaches;
+use Cinema::posters::FilesystemPosterStore;
+use Cinema::repositories::memory::MemoryStore;
+use Cinema::repositories::Repositories;
+use Cinema::routes;
+use Cinema::sessions::SharedSessionStore;
+use Cinema::shutdown::Shutdown;
+use Cinema::state::AppState;
+use Cinema::SESSION_USER_KEY;
+
+const PASSWORD: &str = "correct horse";
+
+struct TestApp {
+    router: Router,
+    store: Arc<MemoryStore>,
+    sessions: SessionMemoryStore,
+}
+
+struct TestResponse {
+    status: StatusCode,
+    headers: axum::http::HeaderMap,
+    body: String,
+}
+
+impl TestResponse {
+    /// The `name=value` of the session cookie the response sets.
+    fn session_cookie(&self) -> Option<String> {
+        let set_cookie = self.headers.get(header::SET_COOKIE)?.to_str().ok()?;
+        set_cookie.split(';').next().map(str::to_string)
+    }
+}
+
+impl TestApp {
+    fn new() -> Self {
+        let store = Arc::new(MemoryStore::new());
+        let sessions = SessionMemoryStore::default();
+        // Only health checks and the audit log use the pools, and no test calls them, so nothing
+        // ever connects.
+        let pool = Pool::builder()
+            .min_idle(Some(0))
+            .build_unchecked(ConnectionManager::<MysqlConnection>::new("mysql://localhost/unused"));
+        let config = AppConfig {
+            // Every request sees the current data.
+            cache: CacheSettings { catalogue_ttl_secs: 0, schedule_ttl_secs: 0 },
+            ..Default::default()
+        };
+        let state = AppState {
+            repos: Repositories::in_memory(store.clone()),
+            pools: Arc::new(DbPools::new(pool, Vec::new())),
+            caches: Arc::new(PageCaches::new(&config.cache)),
+            config: Arc::new(config),
+            availability: AvailabilityFeed::new(),
+            sessions: SharedSessionStore::new(sessions.clone()),
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            posters: Arc::new(FilesystemPosterStore::new(std::env::temp_dir().join("cinema-test-posters"))),
+            shutdown: Shutdown::new(),
+        };
+        // The same store as the state's, as in the server.
+        let session_layer = SessionManagerLayer::new(state.sessions.clone()).with_secure(false);
+
+        TestApp { router: routes::app_router(state).layer(session_layer), store, sessions }
+    }
+
+    async fn send(&self, request: Request<Body>) -> TestResponse {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        TestResponse { status, headers, body: String::from_utf8(bytes.to_vec()).unwrap() }
+    }
+
+    async fn get(&self, uri: &str, cookie: Option<&str>) -> TestResponse {
+        let mut request = Request::get(uri);
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        self.send(request.body(Body::empty()).unwrap()).await
+    }
+
+    async fn submit(&self, method: &str, uri: &str, form: &str, cookie: Option<&str>) -> TestResponse {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        self.send(request.body(Body::from(form.to_string())).unwrap()).await
+    }
+
+    /// Adds an account with [`PASSWORD`], hashed cheaply to keep the tests fast.
+    fn add_user(&self, email: &str) -> User {
+        let hash = bcrypt::hash(PASSWORD, 4).unwrap();
+        self.store.add_user(email, &hash, false)
+    }
+
+    /// Signs in and returns the session cookie.
+    async fn login(&self, email: &str) -> String {
+        let form = format!("email={}&password={}", encode(email), encode(PASSWORD));
+        let response = self.submit("POST", "/login", &form, None).await;
+        assert_eq!(response.headers.get("hx-redirect").unwrap(), "/");
+        response.session_cookie().expect("no session cookie")
+    }
+
+    /// A room of `capacity` seats showing one movie in two days, and the id of that screening.
+    fn add_screening(&self, title: &str, capacity: i32) -> i32 {
+        let movie = self.store.add_movie(title, 1927, "Fritz Lang");
+        let room = self.store.add_room(&format!("Room {}", movie.id), capacity);
+        self.store.add_schedule(movie.id, room.id, in_days(2)).id
+    }
+
+    fn status_of(&self, reservation_id: i32) -> ReservationStatus {
+        let reservations = self.store.reservations();
+        reservations.iter().find(|reservation| reservation.id == reservation_id).unwrap().status
+    }
+}
+
+fn in_days(days: i64) -> NaiveDateTime {
+    Local::now().naive_local() + Duration::days(days)
+}
+
+fn encode(value: &str) -> String {
+    value.replace('@', "%40").replace(' ', "+")
+}
+
+#[tokio::test]
+async fn registering_creates_an_account_once() {
+    let app = TestApp::new();
+    assert_eq!(app.get("/register", None).await.status, StatusCode::OK);
+
+    let form = "email=ada%40example.com&password=correct+horse&password_confirmation=correct+horse";
+    let response = app.submit("POST", "/register", form, None).await;
+    assert_eq!(response.headers.get("hx-redirect").unwrap(), "/login");
+    app.login("ada@example.com").await;
+
+    let again = app.submit("POST", "/register", form, None).await;
+    assert_eq!(again.status, StatusCode::OK);
+    assert!(again.body.contains("Unable to register new user"));
+}
+
+#[tokio::test]
+async fn login_rejects_a_wrong_password() {
+    let app = TestApp::new();
+    app.add_user("ada@example.com");
+
+    let response = app.submit("POST", "/login", "email=ada%40example.com&password=wrong+horse", None).await;
+    assert!(response.headers.get("hx-redirect").is_none());
+    assert!(response.body.contains("Bad login credentials"));
+
+    app.login("ada@example.com").await;
+}
+
+#[tokio::test]
+async fn sessions_keep_only_the_user_id() {
+    let app = TestApp::new();
+    let user = app.add_user("ada@example.com");
+    let cookie = app.login("ada@example.com").await;
+
+    let id = cookie.split_once('=').unwrap().1.parse().unwrap();
+    let record = app.sessions.load(&id).await.unwrap().expect("the session is stored");
+    let data: Vec<_> = record.data.into_iter().collect();
+    assert_eq!(data, [(SESSION_USER_KEY.to_string(), serde_json::json!(user.id))]);
+
+    let page = app.get("/reservations", Some(&cookie)).await;
+    assert_eq!(page.status, StatusCode::OK, "the user is loaded from the id");
+    assert!(page.body.contains("ada@example.com"));
+}
+
+#[tokio::test]
+async fn reservations_need_a_session() {
+    let app = TestApp::new();
+    assert_eq!(app.get("/reservations", None).await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(app.submit("POST", "/reservations", "schedule_id=1", None).await.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn movies_and_the_programme_list_screenings() {
+    let app = TestApp::new();
+    app.add_screening("Metropolis", 10);
+    app.store.add_movie("Nosferatu", 1922, "F. W. Murnau");
+
+    let movies = app.get("/movies?q=metro", None).await;
+    assert_eq!(movies.status, StatusCode::OK);
+    assert!(movies.body.contains("Metropolis"));
+    assert!(!movies.body.contains("Nosferatu"));
+
+    let movie = app.get("/movies/1", None).await;
+    assert!(movie.body.contains("Metropolis"));
+    assert_eq!(app.get("/movies/99", None).await.status, StatusCode::NOT_FOUND);
+    // Page numbers are capped, so that no offset overflows.
+    assert_eq!(app.get("/movies?page=9223372036854775807", None).await.status, StatusCode::OK);
+
+    let programme = app.get(&format!("/programme?date={}", in_days(2).date()), None).await;
+    assert!(programme.body.contains("Metropolis"));
+    assert!(!programme.body.contains("Nosferatu"));
+}
+
+#[tokio::test]
+async fn cancelled_screenings_do_not_count_as_showing_this_week() {
+    let app = TestApp::new();
+    let cancelled = app.add_screening("Metropolis", 10);
+    app.add_screening("Nosferatu", 10);
+    app.store.cancel_schedule(cancelled);
+
+    let movies = app.get("/movies?this_week=true", None).await;
+    assert_eq!(movies.status, StatusCode::OK);
+    assert!(movies.body.contains("Nosferatu"));
+    assert!(!movies.body.contains("Metropolis"));
+}
+
+#[tokio::test]
+async fn creating_a_reservation_lists_it() {
+    let app = TestApp::new();
+    let schedule_id = app.add_screening("Metropolis", 10);
+    app.add_user("ada@example.com");
+    let cookie = app.login("ada@example.com").await;
+
+    let form = app.get("/reservations/new", Some(&cookie)).await;
+    assert!(form.body.contains("Metropolis"));
+
+    let response = app.submit("POST", "/reservations", &format!("schedule_id={schedule_id}"), Some(&cookie)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("Metropolis"));
+    assert_eq!(app.store.active_reservations(schedule_id), 1);
+
+    let listed = app.get("/reservations", Some(&cookie)).await;
+    assert!(listed.body.contains("ada@example.com"));
+}
+
+#[tokio::test]
+async fn booking_the_same_screening_twice_is_refused() {
+    let app = TestApp::new();
+    let schedule_id = app.add_screening("Metropolis", 10);
+    app.add_user("ada@example.com");
+    let cookie = app.login("ada@example.com").await;
+    let form = format!("schedule_id={schedule_id}");
+
+    app.submit("POST", "/reservations", &form, Some(&cookie)).await;
+    let again = app.submit("POST", "/reservations", &form, Some(&cookie)).await;
+
+    assert_eq!(again.status, StatusCode::OK);
+    assert!(again.body.contains("This user already has a reservation for the selected schedule."));
+    assert_eq!(app.store.active_reservations(schedule_id), 1);
+}
+
+#[tokio::test]
+async fn booking_a_full_screening_is_refused() {
+    let app = TestApp::new();
+    let schedule_id = app.add_screening("Metropolis", 1);
+    let form = format!("schedule_id={schedule_id}");
+
+    app.add_user("ada@example.com");
+    let first = app.login("ada@example.com").await;
+    app.submit("POST", "/reservations", &form, Some(&first)).await;
+
+    app.add_user("bob@example.com");
+    let second = app.login("bob@example.com").await;
+    let refused = app.submit("POST", "/reservations", &form, Some(&second)).await;
+
+    assert_eq!(refused.status, StatusCode::OK);
+    assert!(refused.body.contains(&format!("Room capacity exceeded for schedule ID {schedule_id}")));
+    assert_eq!(app.store.active_reservations(schedule_id), 1);
+}
+
+#[tokio::test]
+async fn a_reservation_can_be_moved_and_cancelled() {
+    let app = TestApp::new();
+    let first_id = app.add_screening("Metropolis", 10);
+    let second_id = app.add_screening("Nosferatu", 10);
+    app.add_user("ada@example.com");
+    let cookie = app.login("ada@example.com").await;
+    app.submit("POST", "/reservations", &format!("schedule_id={first_id}"), Some(&cookie)).await;
+    let reservation_id = app.store.reservations()[0].id;
+
+    let edit = app.get(&format!("/reservations/edit/{reservation_id}"), Some(&cookie)).await;
+    assert_eq!(edit.status, StatusCode::OK);
+
+    let moved = app
+        .submit("POST", &format!("/reservations/{reservation_id}"), &format!("schedule_id={second_id}"), Some(&cookie))
+        .await;
+    assert!(moved.body.contains("Nosferatu"));
+    assert_eq!(app.status_of(reservation_id), ReservationStatus::Moved);
+    let moved_id = app.store.reservations().last().unwrap().id;
+    assert_eq!(app.store.active_reservations(second_id), 1);
+
+    let cancelled = app.submit("DELETE", &format!("/reservations/{moved_id}"), "", Some(&cookie)).await;
+    assert_eq!(cancelled.status, StatusCode::OK);
+    assert_eq!(app.status_of(moved_id), ReservationStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn reservations_of_others_cannot_be_changed() {
+    let app = TestApp::new();
+    let schedule_id = app.add_screening("Metropolis", 10);
+    app.add_user("ada@example.com");
+    let owner = app.login("ada@example.com").await;
+    app.submit("POST", "/reservations", &format!("schedule_id={schedule_id}"), Some(&owner)).await;
+    let reservation_id = app.store.reservations()[0].id;
+
+    app.add_user("bob@example.com");
+    let other = app.login("bob@example.com").await;
+    let response = app.submit("DELETE", &format!("/reservations/{reservation_id}"), "", Some(&other)).await;
+
+    assert!(response.status.is_client_error());
+    assert_eq!(app.status_of(reservation_id), ReservationStatus::Active);
+}
+
+#[tokio::test]
+async fn bulk_delete_cancels_the_selected_reservations() {
+    let app = TestApp::new();
+    let first_id = app.add_screening("Metropolis", 10);
+    let second_id = app.add_screening("Nosferatu", 10);
+    let kept_id = app.add_screening("Faust", 10);
+    app.add_user("ada@example.com");
+    let cookie = app.login("ada@example.com").await;
+    for schedule_id in [first_id, second_id, kept_id] {
+        app.submit("POST", "/reservations", &format!("schedule_id={schedule_id}"), Some(&cookie)).await;
+    }
+    let ids: Vec<i32> = app.store.reservations().iter().map(|reservation| reservation.id).collect();
+
+    let form = format!("reservation_ids={}%2C{}&tab=upcoming", ids[0], ids[1]);
+    let response = app.submit("POST", "/reservations/bulk_delete", &form, Some(&cookie)).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(app.status_of(ids[0]), ReservationStatus::Cancelled);
+    assert_eq!(app.status_of(ids[1]), ReservationStatus::Cancelled);
+    assert_eq!(app.status_of(ids[2]), ReservationStatus::Active);
+
+    let malformed = app.submit("POST", "/reservations/bulk_delete", "reservation_ids=one", Some(&cookie)).await;
+    assert_eq!(malformed.status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,35 @@
+//! Checks the order the embedded migrations are applied in. Diesel compares versions as strings,
+//! so `10_…` would run before `2_…`; later migrations are numbered `91_…`, `92_…` and so on.
+
+use diesel::migration::{Migration, MigrationSource};
+use diesel::mysql::Mysql;
+
+use Cinema::migrations::MIGRATIONS;
+
+#[test]
+fn migrations_are_applied_in_the_order_they_were_written() {
+    let mut migrations = MigrationSource::<Mysql>::migrations(&MIGRATIONS).unwrap();
+    // What `MigrationHarness::pending_migrations` does before running them.
+    migrations.sort_unstable_by(|a, b| a.name().version().cmp(&b.name().version()));
+    let names: Vec<String> = migrations.iter().map(|migration| migration.name().to_string()).collect();
+
+    assert_eq!(
+        names,
+        [
+            "0_create_tables",
+            "1_unique_emails",
+            "2_reservation_complex_index",
+            "4_mock_data",
+            "5_movie_posters",
+            "6_movie_search_indexes",
+            "7_schedule_change_cutoff",
+            "8_reservation_history",
+            "9_audit_log",
+            "91_outbox",
+            "92_movie_poster_nullable",
+            "93_sessions",
+            "94_schedule_cancellation",
+            "95_movie_external_id",
+        ]
+    );
+}
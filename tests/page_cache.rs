@@ -0,0 +1,38 @@
+//! Checks which domain events empty which page data caches.
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use Cinema::config::CacheSettings;
+use Cinema::events::DomainEvent;
+use Cinema::page_cache::PageCaches;
+
+/// Reads the `/movies?this_week=true` page through the cache and returns how many loads it took.
+async fn load_this_week(caches: &PageCaches, loads: &AtomicUsize) {
+    caches
+        .movies
+        .get_or_load("/movies?this_week=true".to_string(), || async {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, Infallible>((Vec::new(), 0))
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn schedule_changes_empty_the_catalogue_pages() {
+    let caches = PageCaches::new(&CacheSettings::default());
+    let loads = AtomicUsize::new(0);
+
+    load_this_week(&caches, &loads).await;
+    load_this_week(&caches, &loads).await;
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+    caches.invalidate(&DomainEvent::ReservationCreated { reservation_id: 1, schedule_id: 1, user_id: 1 });
+    load_this_week(&caches, &loads).await;
+    assert_eq!(loads.load(Ordering::SeqCst), 1, "bookings are left to the TTL");
+
+    caches.invalidate(&DomainEvent::ScheduleChanged { schedule_id: 1 });
+    load_this_week(&caches, &loads).await;
+    assert_eq!(loads.load(Ordering::SeqCst), 2);
+}
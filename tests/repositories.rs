@@ -0,0 +1,179 @@
+//! Checks that the in-memory repositories hold the invariants of the MySQL schema, which the
+//! handler tests rely on.
+
+use std::sync::Arc;
+
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use Cinema::audit::{AuditAction, AuditContext};
+use Cinema::config::{BookingWindow, ChangePolicy};
+use Cinema::db::{MovieFilter, MovieSort, ReservationError, ReservationPeriod, ScreeningFilter};
+use Cinema::events::DomainEvent;
+use Cinema::models::{NewReservation, ReservationStatus};
+use Cinema::repositories::memory::MemoryStore;
+use Cinema::repositories::{MovieRepository, ReservationRepository, ScheduleRepository, UserRepository};
+
+fn in_days(days: i64) -> NaiveDateTime {
+    Local::now().naive_local() + Duration::days(days)
+}
+
+fn booking(user_id: i32, schedule_id: i32) -> NewReservation {
+    NewReservation { user_id, schedule_id, changed_by: Some(user_id), moved_from_id: None }
+}
+
+fn audit(user_id: i32) -> AuditContext {
+    AuditContext::new(Some(user_id), None)
+}
+
+/// A store with one movie showing in a room of `capacity` seats in two days.
+fn store_with_screening(capacity: i32) -> (Arc<MemoryStore>, i32) {
+    let store = Arc::new(MemoryStore::new());
+    let movie = store.add_movie("Metropolis", 1927, "Fritz Lang");
+    let room = store.add_room("Room 1", capacity);
+    let schedule = store.add_schedule(movie.id, room.id, in_days(2));
+    (store, schedule.id)
+}
+
+fn is_unique_violation(error: &DieselError) -> bool {
+    matches!(error, DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+}
+
+#[tokio::test]
+async fn registering_a_taken_email_is_a_unique_violation() {
+    let store = MemoryStore::new();
+    let user_id = store.register("ada@example.com".into(), "hash".into(), None).await.unwrap().unwrap();
+
+    let again = store.register("Ada@example.com".into(), "hash".into(), None).await.unwrap();
+    assert!(again.is_err_and(|e| is_unique_violation(&e)));
+
+    let found = store.find_by_email("ada@example.com".into()).await.unwrap().unwrap();
+    assert_eq!(found.map(|user| user.id), Some(user_id));
+    assert_eq!(store.audit_actions(), [AuditAction::Register]);
+    assert_eq!(
+        store.events(),
+        [DomainEvent::UserRegistered { user_id, email: "ada@example.com".into() }]
+    );
+}
+
+#[tokio::test]
+async fn a_user_holds_one_active_reservation_per_screening() {
+    let (store, schedule_id) = store_with_screening(10);
+    let user = store.add_user("ada@example.com", "hash", false);
+    let window = BookingWindow::default();
+
+    let first = store.create(booking(user.id, schedule_id), window, audit(user.id)).await.unwrap().unwrap();
+    let second = store.create(booking(user.id, schedule_id), window, audit(user.id)).await.unwrap();
+    assert!(matches!(second, Err(ReservationError::Database(ref e)) if is_unique_violation(e)));
+
+    // Once cancelled, the seat can be booked again.
+    store.cancel(first, ChangePolicy::default(), audit(user.id)).await.unwrap().unwrap();
+    store.create(booking(user.id, schedule_id), window, audit(user.id)).await.unwrap().unwrap();
+    assert_eq!(store.active_reservations(schedule_id), 1);
+}
+
+#[tokio::test]
+async fn screenings_never_exceed_the_room_capacity() {
+    let (store, schedule_id) = store_with_screening(2);
+    let window = BookingWindow::default();
+
+    for email in ["a@example.com", "b@example.com"] {
+        let user = store.add_user(email, "hash", false);
+        store.create(booking(user.id, schedule_id), window, audit(user.id)).await.unwrap().unwrap();
+    }
+    let late = store.add_user("c@example.com", "hash", false);
+    let refused = store.create(booking(late.id, schedule_id), window, audit(late.id)).await.unwrap();
+
+    assert!(matches!(refused, Err(ReservationError::CapacityExceeded(id)) if id == schedule_id));
+    assert_eq!(store.active_reservations(schedule_id), 2);
+    let screenings = store.live_screenings(ScreeningFilter::default()).await.unwrap().unwrap();
+    assert_eq!(screenings[0].available_seats, 0);
+}
+
+#[tokio::test]
+async fn moving_to_a_full_screening_keeps_the_reservation() {
+    let (store, full_id) = store_with_screening(1);
+    let movie = store.add_movie("Nosferatu", 1922, "F. W. Murnau");
+    let room = store.add_room("Room 2", 5);
+    let other_id = store.add_schedule(movie.id, room.id, in_days(3)).id;
+    let (window, policy) = (BookingWindow::default(), ChangePolicy::default());
+
+    let holder = store.add_user("a@example.com", "hash", false);
+    store.create(booking(holder.id, full_id), window, audit(holder.id)).await.unwrap().unwrap();
+    let mover = store.add_user("b@example.com", "hash", false);
+    let reservation_id = store.create(booking(mover.id, other_id), window, audit(mover.id)).await.unwrap().unwrap();
+
+    let refused = store.move_to(reservation_id, full_id, window, policy, audit(mover.id)).await.unwrap();
+    assert!(matches!(refused, Err(ReservationError::CapacityExceeded(_))));
+    assert!(store.find_active(reservation_id).await.unwrap().is_ok());
+
+    let moved = store.move_to(reservation_id, other_id, window, policy, audit(mover.id)).await.unwrap().unwrap();
+    assert_eq!(moved.id, reservation_id, "moving to the same screening changes nothing");
+}
+
+#[tokio::test]
+async fn bookings_respect_the_window_and_cancelled_screenings() {
+    let (store, schedule_id) = store_with_screening(10);
+    let user = store.add_user("ada@example.com", "hash", false);
+    let narrow = BookingWindow { opens_days_before: 1, closes_minutes_after_start: 15 };
+
+    let early = store.create(booking(user.id, schedule_id), narrow, audit(user.id)).await.unwrap();
+    assert!(matches!(early, Err(ReservationError::BookingNotOpen(_))));
+
+    store.cancel_schedule(schedule_id);
+    let cancelled = store.create(booking(user.id, schedule_id), BookingWindow::default(), audit(user.id)).await.unwrap();
+    assert!(matches!(cancelled, Err(ReservationError::ScreeningCancelled)));
+    assert!(store.reservations().is_empty());
+}
+
+#[tokio::test]
+async fn bulk_cancellation_refuses_reservations_past_their_deadline() {
+    let (store, schedule_id) = store_with_screening(10);
+    let user = store.add_user("ada@example.com", "hash", false);
+    let window = BookingWindow::default();
+    let reservation_id = store.create(booking(user.id, schedule_id), window, audit(user.id)).await.unwrap().unwrap();
+
+    // A cut-off longer than the time left before the screening.
+    let strict = ChangePolicy { default_cutoff_minutes: 3 * 24 * 60 };
+    let outcome = store.cancel_many(vec![reservation_id], strict, audit(user.id)).await.unwrap().unwrap();
+    assert!(outcome.deleted.is_empty());
+    assert_eq!(outcome.refused.len(), 1);
+
+    let outcome = store.cancel_many(vec![reservation_id], ChangePolicy::default(), audit(user.id)).await.unwrap().unwrap();
+    assert_eq!(outcome.deleted, [reservation_id]);
+    assert_eq!(store.reservations()[0].status, ReservationStatus::Cancelled);
+    assert!(!store.belong_to_user(vec![reservation_id], user.id).await.unwrap().unwrap());
+}
+
+#[tokio::test]
+async fn reservations_are_listed_by_period() {
+    let (store, schedule_id) = store_with_screening(10);
+    let user = store.add_user("ada@example.com", "hash", false);
+    let window = BookingWindow::default();
+    let kept = store.create(booking(user.id, schedule_id), window, audit(user.id)).await.unwrap().unwrap();
+
+    let upcoming = store.list_for_user(user.id, ReservationPeriod::Upcoming, None, 20).await.unwrap().unwrap();
+    assert_eq!(upcoming.iter().map(|r| r.reservation_id).collect::<Vec<_>>(), [kept]);
+    assert_eq!(upcoming[0].movie_title, "Metropolis");
+
+    store.cancel(kept, ChangePolicy::default(), audit(user.id)).await.unwrap().unwrap();
+    let history = store.list_for_user(user.id, ReservationPeriod::History, None, 20).await.unwrap().unwrap();
+    assert_eq!(history[0].status, ReservationStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn movie_search_matches_prefixes_case_insensitively() {
+    let store = MemoryStore::new();
+    store.add_movie("Metropolis", 1927, "Fritz Lang");
+    store.add_movie("M", 1931, "Fritz Lang");
+    store.add_movie("Nosferatu", 1922, "F. W. Murnau");
+
+    let filter = MovieFilter { search: Some("fritz".into()), sort: MovieSort::YearAsc, ..Default::default() };
+    let (movies, total) = store.search(filter, 1, 0).await.unwrap().unwrap();
+    assert_eq!(total, 2);
+    assert_eq!(movies[0].title, "Metropolis");
+
+    let filter = MovieFilter { year_to: Some(1925), ..Default::default() };
+    let (movies, _) = store.search(filter, 10, 0).await.unwrap().unwrap();
+    assert_eq!(movies.iter().map(|movie| movie.title.as_str()).collect::<Vec<_>>(), ["Nosferatu"]);
+}
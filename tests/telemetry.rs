@@ -0,0 +1,65 @@
+//! Checks that request spans are exported to the collector at `OTEL_EXPORTER_OTLP_ENDPOINT`, here a
+//! stub recording what it receives. Installs the global subscriber, so it runs alone in its binary.
+
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::http::{Request, StatusCode};
+use axum::routing::{get, post};
+use axum::Router;
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+use tower_http::trace::TraceLayer;
+
+use Cinema::config::AppConfig;
+use Cinema::{request_id, telemetry};
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+#[test]
+fn request_spans_reach_the_collector() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    // SAFETY: no other thread exists yet; the runtime is started below.
+    unsafe { std::env::set_var("OTEL_EXPORTER_OTLP_ENDPOINT", &endpoint) };
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async move {
+        let (exports, mut received) = mpsc::unbounded_channel::<Bytes>();
+        let collector = Router::new().route(
+            "/v1/traces",
+            post(move |body: Bytes| {
+                let _ = exports.send(body);
+                async {}
+            }),
+        );
+        listener.set_nonblocking(true).unwrap();
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        tokio::spawn(async move { axum::serve(listener, collector).await.unwrap() });
+
+        let config = AppConfig::from_env();
+        assert_eq!(config.tracing.otlp_endpoint.as_deref(), Some(endpoint.as_str()));
+        let telemetry = telemetry::init(&config).unwrap();
+
+        // The layers the server puts around its routes to give each request its span.
+        let app = Router::new()
+            .route("/telemetry-probe", get(|| async { "ok" }))
+            .layer(TraceLayer::new_for_http().make_span_with(request_id::RequestSpan))
+            .layer(axum::middleware::from_fn(request_id::propagate));
+        let request = Request::get("/telemetry-probe").body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::OK);
+
+        // Flushes the batched spans, as the server does before exiting.
+        tokio::task::spawn_blocking(move || telemetry.shutdown()).await.unwrap();
+
+        let export = tokio::time::timeout(Duration::from_secs(10), received.recv())
+            .await
+            .expect("no spans reached the collector")
+            .unwrap();
+        assert!(contains(&export, b"request"), "the request span is exported");
+        assert!(contains(&export, b"/telemetry-probe"), "with the request's URI");
+        assert!(contains(&export, b"cinema"), "under the default service name");
+    });
+}